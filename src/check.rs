@@ -0,0 +1,175 @@
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use chrono::{Local, TimeZone};
+
+use crate::Error;
+use crate::{TrashDir, TrashInfo};
+
+/// An inconsistency in a trash directory
+#[derive(Debug)]
+pub enum Problem {
+    /// A file in `$trash/files` that has no .trashinfo file.
+    OrphanedFile(PathBuf),
+
+    /// A .trashinfo file whose file in `$trash/files` is missing.
+    DanglingInfo(PathBuf),
+
+    /// A .trashinfo file that can't be read, along with the file it belongs to.
+    CorruptInfo(PathBuf, PathBuf, Error),
+
+    /// Something in `$trash/info` that isn't a .trashinfo file.
+    StrayInfo(PathBuf),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::OrphanedFile(path) => write!(f, "{} has no .trashinfo file", path.display()),
+            Problem::DanglingInfo(path) => {
+                write!(f, "{} has no file in the trash", path.display())
+            }
+            Problem::CorruptInfo(path, _, err) => {
+                write!(f, "{} is corrupt: {}", path.display(), err)
+            }
+            Problem::StrayInfo(path) => write!(f, "{} isn't a .trashinfo file", path.display()),
+        }
+    }
+}
+
+impl Problem {
+    /// Describe what `repair` will do about this problem.
+    pub fn repair_action(&self) -> &'static str {
+        match self {
+            Problem::OrphanedFile(_) => "regenerate its .trashinfo file",
+            Problem::DanglingInfo(_) => "remove the .trashinfo file",
+            Problem::CorruptInfo(..) | Problem::StrayInfo(_) => "move it into quarantine",
+        }
+    }
+
+    /// Fix this problem.
+    ///
+    /// - Orphaned files get a new .trashinfo file, dated when the file was last
+    ///   changed. Since the original path is lost, they're restored into your home
+    ///   directory (or the top of the mount, for topdir trash directories).
+    /// - Dangling .trashinfo files are removed.
+    /// - Corrupt .trashinfo files (and their files) and anything else that doesn't
+    ///   belong in `$trash/info` are moved to `$trash/quarantine`, where they aren't
+    ///   touched by anything else.
+    pub fn repair(&self, trash_dir: &TrashDir) -> Result<(), Error> {
+        match self {
+            Problem::OrphanedFile(path) => regenerate_info(trash_dir, path),
+            Problem::DanglingInfo(path) => Ok(fs::remove_file(path)?),
+            Problem::CorruptInfo(info_path, deleted_path, _) => {
+                quarantine(trash_dir, info_path)?;
+                if deleted_path.symlink_metadata().is_ok() {
+                    quarantine(trash_dir, deleted_path)?;
+                }
+                Ok(())
+            }
+            Problem::StrayInfo(path) => quarantine(trash_dir, path),
+        }
+    }
+}
+
+impl TrashDir {
+    /// Scan this trash directory for inconsistencies between `$trash/info` and `$trash/files`.
+    pub fn check(&self) -> Result<Vec<Problem>, Error> {
+        let info_dir = self.path().join("info");
+        let files_dir = self.path().join("files");
+        let topdir = self.topdir();
+        let mut problems = Vec::new();
+
+        if info_dir.is_dir() {
+            for entry in fs::read_dir(&info_dir)? {
+                let path = entry?.path();
+                let name = match (path.extension(), path.file_stem()) {
+                    (Some(ext), Some(name)) if ext == "trashinfo" && path.is_file() => name,
+                    _ => {
+                        problems.push(Problem::StrayInfo(path));
+                        continue;
+                    }
+                };
+
+                let deleted_path = files_dir.join(name);
+                match TrashInfo::from_files(&path, &deleted_path, topdir.as_deref()) {
+                    Ok(_) if deleted_path.symlink_metadata().is_err() => {
+                        problems.push(Problem::DanglingInfo(path))
+                    }
+                    Ok(_) => (),
+                    Err(err) => problems.push(Problem::CorruptInfo(path, deleted_path, err)),
+                }
+            }
+        }
+
+        if files_dir.is_dir() {
+            for entry in fs::read_dir(&files_dir)? {
+                let path = entry?.path();
+                let mut info_name = path.file_name().unwrap_or_default().to_os_string();
+                info_name.push(".trashinfo");
+                if info_dir.join(info_name).symlink_metadata().is_err() {
+                    problems.push(Problem::OrphanedFile(path));
+                }
+            }
+        }
+
+        Ok(problems)
+    }
+}
+
+/// Write a new .trashinfo file for a file in `$trash/files` that lost its own.
+fn regenerate_info(trash_dir: &TrashDir, deleted_path: &Path) -> Result<(), Error> {
+    let name = deleted_path.file_name().unwrap_or_default();
+    let mut info_name = name.to_os_string();
+    info_name.push(".trashinfo");
+    let info_path = trash_dir.info_dir()?.join(info_name);
+
+    let topdir = trash_dir.topdir();
+    let restore_dir = match topdir {
+        Some(ref topdir) => topdir.clone(),
+        None => env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("/")),
+    };
+    let changed = deleted_path.symlink_metadata()?.ctime();
+
+    let info = TrashInfo {
+        path: restore_dir.join(name),
+        deletion_date: Local
+            .timestamp_opt(changed, 0)
+            .single()
+            .unwrap_or_else(Local::now),
+        deleted_path: deleted_path.to_path_buf(),
+        info_path: info_path.clone(),
+        topdir,
+        cached_size: None,
+    };
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&info_path)?;
+    info.write(&file)?;
+    Ok(())
+}
+
+/// Move something out of the way into `$trash/quarantine`.
+fn quarantine(trash_dir: &TrashDir, path: &Path) -> Result<(), Error> {
+    let quarantine_dir = trash_dir.path().join("quarantine");
+    fs::create_dir_all(&quarantine_dir)?;
+
+    let name = path.file_name().unwrap_or_default();
+    let mut target = quarantine_dir.join(name);
+    let mut counter = 1;
+    while target.symlink_metadata().is_ok() {
+        counter += 1;
+        let mut candidate = name.to_os_string();
+        candidate.push(format!(".{}", counter));
+        target = quarantine_dir.join(candidate);
+    }
+
+    fs::rename(path, target)?;
+    Ok(())
+}
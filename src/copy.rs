@@ -0,0 +1,339 @@
+//! Copying files into and out of the trash, keeping everything about them intact.
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, MetadataExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::Mutex;
+
+use anyhow::Result;
+
+use crate::utils;
+use crate::walk::Walker;
+
+/// `_IOW(0x94, 9, int)` from linux/fs.h, which isn't in the libc crate
+const FICLONE: libc::c_ulong = 0x4004_9409;
+
+/// How much `copy_file_range` is asked to copy at once
+const COPY_CHUNK_SIZE: usize = 1 << 30;
+
+/// The size of the buffer used when the kernel can't copy by itself
+const BUFFER_SIZE: usize = 128 * 1024;
+
+/// Recursively copies src to dst, which mustn't exist yet.
+///
+/// Besides the contents, this keeps the mode, ownership (if we're allowed to change
+/// it), access and modification times and extended attributes (which includes ACLs) of
+/// everything that's copied. Symlinks are copied as symlinks, FIFOs and device nodes are
+/// recreated, and files that are hard links to each other stay hard links in the copy.
+pub fn recursive_copy(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<()> {
+    let src = src.as_ref();
+    let dst = dst.as_ref();
+
+    let meta = src.symlink_metadata()?;
+    if !meta.is_dir() {
+        copy_entry(src, dst, &meta)?;
+        return Ok(copy_metadata(src, dst, &meta)?);
+    }
+    fs::create_dir(dst)?;
+
+    // files with more than one link are copied afterwards, one at a time, so every
+    // link after the first one can point to the first copy
+    let linked = Mutex::new(Vec::new());
+    // directories get their metadata last, since copying their contents changes it
+    let dirs = Mutex::new(vec![(0, src.to_path_buf(), dst.to_path_buf(), meta)]);
+
+    Walker::new().same_file_system(true).run(src, |entry| {
+        let relative_path = entry.path.strip_prefix(src).map_err(io::Error::other)?;
+        let target = dst.join(relative_path);
+        let meta = &entry.metadata;
+
+        if meta.is_dir() {
+            fs::create_dir(&target)?;
+            let dir = (entry.depth, entry.path.clone(), target, meta.clone());
+            dirs.lock().unwrap().push(dir);
+        } else if meta.nlink() > 1 {
+            let file = (entry.path.clone(), target, meta.clone());
+            linked.lock().unwrap().push(file);
+        } else {
+            copy_entry(&entry.path, &target, meta)?;
+            copy_metadata(&entry.path, &target, meta)?;
+        }
+        Ok(())
+    })?;
+
+    // (dev, ino) of the files with more than one link, and where they were copied to
+    let mut links = HashMap::<(u64, u64), PathBuf>::new();
+    let mut linked = linked.into_inner().unwrap();
+    linked.sort_by(|(a, ..), (b, ..)| a.cmp(b));
+    for (path, target, meta) in linked {
+        match links.get(&(meta.dev(), meta.ino())) {
+            Some(original) => fs::hard_link(original, &target)?,
+            None => {
+                copy_entry(&path, &target, &meta)?;
+                copy_metadata(&path, &target, &meta)?;
+                links.insert((meta.dev(), meta.ino()), target);
+            }
+        }
+    }
+
+    // deepest first, so nothing changes a directory after its times are set
+    let mut dirs = dirs.into_inner().unwrap();
+    dirs.sort_by(|(a, ..), (b, ..)| b.cmp(a));
+    for (_, path, target, meta) in dirs {
+        copy_metadata(&path, &target, &meta)?;
+    }
+
+    Ok(())
+}
+
+/// Recursively copies src to the temporary path temp, and then calls `place` to move
+/// the copy to where it belongs.
+///
+/// If either of them fails, whatever was copied is removed again, so there's never a
+/// partial copy left behind.
+pub fn copy_into_place(
+    src: impl AsRef<Path>,
+    temp: impl AsRef<Path>,
+    place: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    let temp = temp.as_ref();
+    let result = recursive_copy(src, temp).and_then(|_| place());
+    if result.is_err() && temp.symlink_metadata().is_ok() {
+        if let Err(err) = utils::force_remove_all(temp) {
+            eprintln!(
+                "failed to remove the partial copy {}: {}",
+                temp.display(),
+                err
+            );
+        }
+    }
+    result
+}
+
+/// Creates a copy of a single non-directory at dst.
+fn copy_entry(src: &Path, dst: &Path, meta: &Metadata) -> io::Result<()> {
+    let file_type = meta.file_type();
+    if file_type.is_file() {
+        let mut input = File::open(src)?;
+        // the real mode is set after the extended attributes, which may need write access
+        let mut output = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(dst)?;
+        copy_contents(&mut input, &mut output)?;
+
+        // the source is removed after this, so a short copy must never go unnoticed
+        let (copied, len) = (output.metadata()?.len(), input.metadata()?.len());
+        if copied < len {
+            return Err(io::Error::other(format!(
+                "only copied {} of {} bytes",
+                copied, len
+            )));
+        }
+    } else if file_type.is_symlink() {
+        symlink(fs::read_link(src)?, dst)?;
+    } else {
+        // FIFOs, sockets and device nodes
+        let path = to_cstring(dst)?;
+        let ret = unsafe { libc::mknod(path.as_ptr(), meta.mode(), meta.rdev()) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Copies the contents of one file into another, as cheaply as the filesystems allow.
+///
+/// This first tries to reflink the whole file, which shares its blocks on filesystems
+/// like btrfs and XFS. Then it tries `copy_file_range`, which copies within the kernel
+/// (and on some filesystems on the server side, or with a reflink after all). If
+/// neither works, the contents are copied through a buffer.
+///
+/// Some filesystems (like procfs, and some FUSE and NFS setups) claim that a file is
+/// empty to `copy_file_range`, so the buffered copy is also used when it copies
+/// nothing at all.
+fn copy_contents(input: &mut File, output: &mut File) -> io::Result<()> {
+    let ret = unsafe { libc::ioctl(output.as_raw_fd(), FICLONE as _, input.as_raw_fd()) };
+    if ret == 0 {
+        return Ok(());
+    }
+
+    let mut copied_any = false;
+    loop {
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_copy_file_range,
+                input.as_raw_fd(),
+                ptr::null_mut::<libc::loff_t>(),
+                output.as_raw_fd(),
+                ptr::null_mut::<libc::loff_t>(),
+                COPY_CHUNK_SIZE,
+                0,
+            )
+        };
+        match ret {
+            0 if copied_any => return Ok(()),
+            0 => break,
+            n if n > 0 => {
+                copied_any = true;
+                continue;
+            }
+            _ => (),
+        }
+
+        // the file offsets have moved along, so the buffered copy picks up from there
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::ENOSYS)
+            | Some(libc::EXDEV)
+            | Some(libc::EINVAL)
+            | Some(libc::EOPNOTSUPP)
+            | Some(libc::EPERM) => break,
+            Some(libc::EINTR) => continue,
+            _ => return Err(err),
+        }
+    }
+
+    let mut buf = vec![0; BUFFER_SIZE];
+    loop {
+        let len = match input.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        output.write_all(&buf[..len])?;
+    }
+}
+
+/// Gives dst the same extended attributes, owner, mode and times as src.
+fn copy_metadata(src: &Path, dst: &Path, meta: &Metadata) -> io::Result<()> {
+    let path = to_cstring(dst)?;
+    let is_symlink = meta.file_type().is_symlink();
+
+    copy_xattrs(&to_cstring(src)?, &path)?;
+
+    // only root can give files away, so this failing isn't an error
+    let ret = unsafe { libc::lchown(path.as_ptr(), meta.uid(), meta.gid()) };
+    if ret != 0 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EPERM) {
+            return Err(err);
+        }
+    }
+
+    // symlinks don't have a mode of their own, and chmod would follow them
+    if !is_symlink {
+        let ret = unsafe { libc::chmod(path.as_ptr(), meta.mode() & 0o7777) };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    let times = [
+        libc::timespec {
+            tv_sec: meta.atime() as libc::time_t,
+            tv_nsec: meta.atime_nsec() as libc::c_long,
+        },
+        libc::timespec {
+            tv_sec: meta.mtime() as libc::time_t,
+            tv_nsec: meta.mtime_nsec() as libc::c_long,
+        },
+    ];
+    let ret = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Copies every extended attribute of src to dst, without following symlinks.
+///
+/// Attributes that the destination filesystem doesn't support or that we aren't
+/// allowed to set (like `security.*` or `trusted.*` ones) are skipped.
+fn copy_xattrs(src: &CString, dst: &CString) -> io::Result<()> {
+    let names = match read_xattr(|buf, len| unsafe {
+        libc::llistxattr(src.as_ptr(), buf as *mut libc::c_char, len)
+    }) {
+        Ok(names) => names,
+        Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for name in names.split(|b| *b == 0).filter(|name| !name.is_empty()) {
+        let name =
+            CString::new(name).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let value = match read_xattr(|buf, len| unsafe {
+            libc::lgetxattr(src.as_ptr(), name.as_ptr(), buf, len)
+        }) {
+            Ok(value) => value,
+            // it was removed in the meantime
+            Err(err) if err.raw_os_error() == Some(libc::ENODATA) => continue,
+            Err(err) => return Err(err),
+        };
+
+        let ret = unsafe {
+            libc::lsetxattr(
+                dst.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr() as *const libc::c_void,
+                value.len(),
+                0,
+            )
+        };
+        if ret != 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::ENOTSUP) | Some(libc::EPERM) => (),
+                _ => return Err(err),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Calls one of the xattr functions that fill a buffer, growing the buffer until the
+/// result fits.
+fn read_xattr(f: impl Fn(*mut libc::c_void, usize) -> isize) -> io::Result<Vec<u8>> {
+    loop {
+        let len = f(ptr::null_mut(), 0);
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buf = vec![0u8; len as usize];
+        let len = f(buf.as_mut_ptr() as *mut libc::c_void, buf.len());
+        if len >= 0 {
+            buf.truncate(len as usize);
+            return Ok(buf);
+        }
+
+        // it grew between the two calls
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+fn to_cstring(path: &Path) -> io::Result<CString> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
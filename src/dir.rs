@@ -1,11 +1,15 @@
-use std::fs;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, OpenOptions};
+use std::io;
 use std::path::{Path, PathBuf};
 
 use walkdir::{DirEntry, WalkDir};
 
+use crate::utils;
+use crate::DirectorySizes;
 use crate::Error;
 use crate::TrashInfo;
-use crate::XDG;
+use crate::{MOUNTS, XDG};
 
 /// A trash directory represented by a path.
 #[derive(Clone, Debug)]
@@ -32,15 +36,26 @@ impl TrashDir {
     ///
     /// If the option is None, then the home trash will be selected instead.
     pub fn from_opt(opt: Option<impl AsRef<Path>>) -> Self {
-        opt.map(|path| TrashDir::from(path.as_ref().to_path_buf()))
-            .unwrap_or_else(|| TrashDir::get_home_trash())
+        opt.map(TrashDir::from)
+            .unwrap_or_else(TrashDir::get_home_trash)
+    }
+
+    /// Pick the trash directories to operate on from an optional path
+    ///
+    /// If the option is None, then every trash directory of the current user is
+    /// selected, across all mounts.
+    pub fn all_from_opt(opt: Option<impl AsRef<Path>>) -> Vec<Self> {
+        match opt {
+            Some(path) => vec![TrashDir::from(path)],
+            None => MOUNTS.trash_dirs(),
+        }
     }
 
     /// Actually create the directory on disk corresponding to this trash directory
     pub fn create(&self) -> Result<(), Error> {
         let path = &self.0;
         if !path.exists() {
-            fs::create_dir_all(&path)?;
+            fs::create_dir_all(path)?;
         }
         Ok(())
     }
@@ -50,6 +65,29 @@ impl TrashDir {
         self.0.as_ref()
     }
 
+    /// Gets the top directory of the mount this trash directory lives on, if this
+    /// is a `$topdir/.Trash/$uid` or `$topdir/.Trash-$uid` trash directory.
+    ///
+    /// According to Trash spec v1.0, paths stored in these trash directories are
+    /// relative to `$topdir`. The topdir is derived from where the trash directory
+    /// is right now, so it follows the mount point if the drive moves around.
+    pub fn topdir(&self) -> Option<PathBuf> {
+        let uid = utils::get_uid().to_string();
+        let path = utils::into_absolute(&self.0).ok()?;
+        let name = path.file_name()?;
+        let parent = path.parent()?;
+
+        if name == OsStr::new(&format!(".Trash-{}", uid)) {
+            return Some(parent.to_path_buf());
+        }
+
+        if name == OsStr::new(&uid) && parent.file_name() == Some(OsStr::new(".Trash")) {
+            return parent.parent().map(Path::to_path_buf);
+        }
+
+        None
+    }
+
     /// Get the `files` directory
     pub fn files_dir(&self) -> Result<PathBuf, Error> {
         let target = self.0.join("files");
@@ -78,6 +116,48 @@ impl TrashDir {
         }
     }
 
+    /// Reserve a unique name for a new entry in this trash directory.
+    ///
+    /// According to Trash spec v1.0, the `.trashinfo` file is created first with
+    /// `O_CREAT|O_EXCL`, so two processes can never end up with the same name. If the
+    /// name is taken, a numeric suffix is added and the next one is tried. Returns the
+    /// reserved name along with the newly created (empty) `.trashinfo` file.
+    pub fn reserve_name(&self, name: &OsStr) -> Result<(OsString, File), Error> {
+        let files_dir = self.files_dir()?;
+        let info_dir = self.info_dir()?;
+
+        let mut counter = 1;
+        loop {
+            let mut candidate = name.to_os_string();
+            if counter > 1 {
+                candidate.push(format!(".{}", counter));
+            }
+            counter += 1;
+
+            let mut info_name = candidate.clone();
+            info_name.push(".trashinfo");
+            let info_path = info_dir.join(info_name);
+
+            let file = match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                Ok(file) => file,
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(Error::from(err)),
+            };
+
+            // a leftover file without a .trashinfo still occupies this name
+            if files_dir.join(&candidate).symlink_metadata().is_ok() {
+                fs::remove_file(&info_path)?;
+                continue;
+            }
+
+            return Ok((candidate, file));
+        }
+    }
+
     /// Iterate over trash infos within this trash directory
     pub fn iter(&self) -> Result<TrashDirIter, Error> {
         let iter = WalkDir::new(&self.info_dir()?)
@@ -87,11 +167,21 @@ impl TrashDir {
                 Some(x) => x == "trashinfo",
                 _ => false,
             });
-        Ok(TrashDirIter(self.0.clone(), Box::new(iter)))
+        Ok(TrashDirIter {
+            path: self.0.clone(),
+            topdir: self.topdir(),
+            sizes: DirectorySizes::read(self)?,
+            entries: Box::new(iter),
+        })
     }
 }
 
-pub struct TrashDirIter(PathBuf, Box<dyn Iterator<Item = walkdir::Result<DirEntry>>>);
+pub struct TrashDirIter {
+    path: PathBuf,
+    topdir: Option<PathBuf>,
+    sizes: DirectorySizes,
+    entries: Box<dyn Iterator<Item = walkdir::Result<DirEntry>>>,
+}
 
 impl Iterator for TrashDirIter {
     type Item = Result<TrashInfo, Error>;
@@ -100,7 +190,7 @@ impl Iterator for TrashDirIter {
         let entry = {
             let mut entry;
             loop {
-                entry = match self.1.next() {
+                entry = match self.entries.next() {
                     Some(Ok(entry)) => entry,
                     Some(Err(err)) => return Some(Err(Error::from(err))),
                     None => return None,
@@ -113,14 +203,15 @@ impl Iterator for TrashDirIter {
             entry
         };
 
-        let name = entry.path().file_name().unwrap().to_str().unwrap();
-        let deleted_path = if !name.ends_with(".trashinfo") {
-            return self.next();
-        } else {
-            self.0
-                .join("files")
-                .join(name.trim_end_matches(".trashinfo"))
+        let path = entry.path();
+        let deleted_path = match (path.extension(), path.file_stem()) {
+            (Some(ext), Some(name)) if ext == "trashinfo" => self.path.join("files").join(name),
+            _ => return self.next(),
         };
-        Some(TrashInfo::from_files(entry.path(), deleted_path).map_err(Error::from))
+        let info = TrashInfo::from_files(path, deleted_path, self.topdir.as_deref());
+        Some(info.map(|mut info| {
+            info.cached_size = self.sizes.get(&info);
+            info
+        }))
     }
 }
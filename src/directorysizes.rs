@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::info::{decode_path, encode_path};
+use crate::walk::Walker;
+use crate::Error;
+use crate::{TrashDir, TrashInfo};
+
+/// A single entry of the `directorysizes` cache.
+#[derive(Clone, Copy, Debug)]
+struct DirectorySize {
+    size: u64,
+    mtime: i64,
+}
+
+/// The `directorysizes` cache of a trash directory.
+///
+/// According to Trash spec v1.0, this file lists the size of every directory in
+/// `$trash/files`, along with the mtime of its `.trashinfo` file, so that a file
+/// manager doesn't have to walk every trashed directory to show the trash size.
+///
+/// Each line is `[size] [mtime] [percent-encoded name]`. The cache is always
+/// rewritten to a temporary file and then atomically renamed over the old one.
+#[derive(Debug, Default)]
+pub struct DirectorySizes(BTreeMap<OsString, DirectorySize>);
+
+impl DirectorySizes {
+    /// Read the `directorysizes` cache of a trash directory.
+    ///
+    /// A missing cache is treated as empty, and malformed lines are skipped.
+    pub fn read(trash_dir: &TrashDir) -> Result<Self, Error> {
+        let file = match File::open(trash_dir.path().join("directorysizes")) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(DirectorySizes::default()),
+            Err(err) => return Err(Error::from(err)),
+        };
+
+        let mut entries = BTreeMap::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let mut parts = line.splitn(3, ' ');
+            let (size, mtime, name) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(size), Some(mtime), Some(name)) => (size, mtime, name),
+                _ => continue,
+            };
+            let (size, mtime) = match (size.parse(), mtime.parse()) {
+                (Ok(size), Ok(mtime)) => (size, mtime),
+                _ => continue,
+            };
+            let name = decode_path(name).into_os_string();
+            entries.insert(name, DirectorySize { size, mtime });
+        }
+
+        Ok(DirectorySizes(entries))
+    }
+
+    /// Write the cache back to the trash directory.
+    ///
+    /// Entries for directories that no longer exist in `$trash/files` are dropped.
+    pub fn write(&self, trash_dir: &TrashDir) -> Result<(), Error> {
+        let files_dir = trash_dir.files_dir()?;
+        let target = trash_dir.path().join("directorysizes");
+        let temp = trash_dir
+            .path()
+            .join(format!("directorysizes.{}", process::id()));
+
+        {
+            let mut out = File::create(&temp)?;
+            for (name, entry) in self.0.iter() {
+                if !files_dir.join(name).is_dir() {
+                    continue;
+                }
+                writeln!(
+                    out,
+                    "{} {} {}",
+                    entry.size,
+                    entry.mtime,
+                    encode_path(Path::new(name))
+                )?;
+            }
+            out.sync_all()?;
+        }
+
+        fs::rename(&temp, &target)?;
+        Ok(())
+    }
+
+    /// Look up the cached size of a trashed directory.
+    ///
+    /// This returns None if there's no entry, or if the entry is stale (the mtime
+    /// doesn't match the entry's `.trashinfo` file anymore).
+    pub fn get(&self, info: &TrashInfo) -> Option<u64> {
+        let entry = self.0.get(info.deleted_path.file_name()?)?;
+        let mtime = info.info_path.metadata().ok()?.mtime();
+        if entry.mtime == mtime {
+            Some(entry.size)
+        } else {
+            None
+        }
+    }
+
+    /// Record the size of a trashed entry, if it's a directory.
+    pub fn insert(&mut self, info: &TrashInfo) -> Result<(), Error> {
+        let name = match info.deleted_path.file_name() {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        if !info.deleted_path.symlink_metadata()?.is_dir() {
+            return Ok(());
+        }
+
+        let size = disk_usage(&info.deleted_path)?;
+        let mtime = info.info_path.metadata()?.mtime();
+        self.0
+            .insert(name.to_os_string(), DirectorySize { size, mtime });
+        Ok(())
+    }
+
+    /// Forget about a trashed entry.
+    pub fn remove(&mut self, name: &OsStr) {
+        self.0.remove(name);
+    }
+
+    /// Read the cache, apply `f` to it, and write it back.
+    pub fn update(
+        trash_dir: &TrashDir,
+        f: impl FnOnce(&mut DirectorySizes) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut sizes = DirectorySizes::read(trash_dir)?;
+        f(&mut sizes)?;
+        sizes.write(trash_dir)
+    }
+}
+
+/// Add up how much space a directory takes up on disk, like `du -B1` does.
+///
+/// This is what the spec asks `directorysizes` to record: the allocated blocks of
+/// everything inside, and of the directory itself. Symlinks aren't followed.
+pub fn disk_usage(path: impl AsRef<Path>) -> Result<u64, Error> {
+    let path = path.as_ref();
+    let meta = path.symlink_metadata()?;
+    if !meta.is_dir() {
+        return Ok(meta.blocks() * 512);
+    }
+
+    let total = AtomicU64::new(meta.blocks() * 512);
+    Walker::new().run(path, |entry| {
+        total.fetch_add(entry.metadata.blocks() * 512, Ordering::Relaxed);
+        Ok(())
+    })?;
+    Ok(total.into_inner())
+}
+
+/// Add up the sizes of everything inside a directory, without following symlinks.
+pub fn apparent_size(path: impl AsRef<Path>) -> Result<u64, Error> {
+    let path = path.as_ref();
+    let meta = path.symlink_metadata()?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+
+    let total = AtomicU64::new(0);
+    Walker::new().run(path, |entry| {
+        if !entry.metadata.is_dir() {
+            total.fetch_add(entry.metadata.len(), Ordering::Relaxed);
+        }
+        Ok(())
+    })?;
+    Ok(total.into_inner())
+}
@@ -1,3 +1,7 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
 /// All errors that could happen
 #[derive(Debug, Error)]
 #[allow(missing_docs)]
@@ -6,15 +10,27 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("Walkdir error: {0}")]
     WalkDir(#[from] walkdir::Error),
+    #[error("{0}")]
+    Walk(#[from] WalkErrors),
     #[error("Bad .trashinfo file: {0}")]
     BadTrashInfo(#[from] TrashInfoError),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
     #[error("Date parsing error: {0}")]
     ParseDate(#[from] chrono::format::ParseError),
+    #[error("Invalid duration '{0}', expected something like 30d, 2w or 36h")]
+    InvalidDuration(String),
+    #[error("Invalid date '{0}', expected something like 2026-01-01 or 2w")]
+    InvalidDate(String),
+    #[error("Unknown file type '{0}', expected one of: file, directory, symlink, other")]
+    UnknownFileKind(String),
+    #[error("Unknown sort key '{0}', expected one of: date, path, size, name")]
+    UnknownSortKey(String),
 }
 
 /// Errors related to .trashinfo files
 #[derive(Debug, Error)]
-#[allow(missing_docs)]
+#[allow(missing_docs, clippy::enum_variant_names)]
 pub enum TrashInfoError {
     #[error("Missing [TrashInfo] header")]
     MissingHeader,
@@ -23,3 +39,28 @@ pub enum TrashInfoError {
     #[error("Missing date attribute")]
     MissingDate,
 }
+
+/// Everything that went wrong while walking a directory tree, sorted by path
+#[derive(Debug)]
+pub struct WalkErrors(pub Vec<(PathBuf, io::Error)>);
+
+impl WalkErrors {
+    /// Check whether any of the errors is of this kind.
+    pub fn any_of_kind(&self, kind: io::ErrorKind) -> bool {
+        self.0.iter().any(|(_, err)| err.kind() == kind)
+    }
+}
+
+impl fmt::Display for WalkErrors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (path, err)) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {}", path.display(), err)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for WalkErrors {}
@@ -0,0 +1,123 @@
+use std::cmp::Reverse;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::{DateTime, Local};
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+
+use crate::Error;
+use crate::{FileKind, TrashInfo};
+
+/// A condition that trash entries can be picked out by.
+#[derive(Debug)]
+pub enum Filter {
+    /// The original path matches this glob.
+    ///
+    /// If the pattern doesn't contain a `/`, only the file name is matched against it.
+    Glob(Pattern),
+
+    /// The original path matches this regular expression.
+    Regex(Regex),
+
+    /// The original path is inside of this directory.
+    Under(PathBuf),
+
+    /// The file was deleted before this date.
+    DeletedBefore(DateTime<Local>),
+
+    /// The file was deleted after this date.
+    DeletedAfter(DateTime<Local>),
+
+    /// The file is at least this many bytes.
+    MinSize(u64),
+
+    /// The file is at most this many bytes.
+    MaxSize(u64),
+
+    /// The file is of this kind.
+    Kind(FileKind),
+
+    /// Any one of these filters matches.
+    Any(Vec<Filter>),
+}
+
+impl Filter {
+    /// Check whether a trash entry matches this filter.
+    ///
+    /// Entries whose size or kind can't be determined never match a size or kind filter.
+    pub fn matches(&self, info: &TrashInfo) -> bool {
+        match self {
+            Filter::Glob(pattern) => {
+                let match_options = MatchOptions {
+                    require_literal_separator: true,
+                    ..MatchOptions::new()
+                };
+                if pattern.as_str().contains('/') {
+                    pattern.matches_path_with(&info.path, match_options)
+                } else {
+                    info.path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .map(|name| pattern.matches_with(name, match_options))
+                        .unwrap_or(false)
+                }
+            }
+            Filter::Regex(regex) => regex.is_match(&info.path.to_string_lossy()),
+            Filter::Under(dir) => info.path.starts_with(dir) && info.path != *dir,
+            Filter::DeletedBefore(date) => info.deletion_date < *date,
+            Filter::DeletedAfter(date) => info.deletion_date > *date,
+            Filter::MinSize(size) => info.size().map(|s| s >= *size).unwrap_or(false),
+            Filter::MaxSize(size) => info.size().map(|s| s <= *size).unwrap_or(false),
+            Filter::Kind(kind) => info.file_kind().map(|k| k == *kind).unwrap_or(false),
+            Filter::Any(filters) => filters.iter().any(|filter| filter.matches(info)),
+        }
+    }
+
+    /// Check whether a trash entry matches every one of the given filters.
+    pub fn matches_all(filters: &[Filter], info: &TrashInfo) -> bool {
+        filters.iter().all(|filter| filter.matches(info))
+    }
+}
+
+/// What to sort trash entries by
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    /// Deletion date, oldest first
+    Date,
+
+    /// Original path
+    Path,
+
+    /// Size, largest first
+    Size,
+
+    /// File name of the original path
+    Name,
+}
+
+impl SortKey {
+    /// Sort trash entries by this key.
+    pub fn sort(self, files: &mut [TrashInfo]) {
+        match self {
+            SortKey::Date => files.sort_by_key(|info| info.deletion_date),
+            SortKey::Path => files.sort_by(|a, b| a.path.cmp(&b.path)),
+            SortKey::Size => files.sort_by_cached_key(|info| Reverse(info.size().unwrap_or(0))),
+            SortKey::Name => files.sort_by(|a, b| a.path.file_name().cmp(&b.path.file_name())),
+        }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "date" => Ok(SortKey::Date),
+            "path" => Ok(SortKey::Path),
+            "size" => Ok(SortKey::Size),
+            "name" => Ok(SortKey::Name),
+            _ => Err(Error::UnknownSortKey(s.to_owned())),
+        }
+    }
+}
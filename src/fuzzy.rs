@@ -0,0 +1,177 @@
+//! Fuzzy matching of queries against paths.
+
+use std::cmp::Reverse;
+
+/// Score of every matched character
+const MATCH: i64 = 16;
+
+/// Extra score for a character right after the previous matched one
+const CONSECUTIVE: i64 = 12;
+
+/// Extra score for a character at the start of a word, like right after a `/`
+const WORD_START: i64 = 8;
+
+/// Extra score for a character in the last component of the path
+const FILE_NAME: i64 = 4;
+
+/// Penalty for every character skipped between two matched ones
+const GAP: i64 = 1;
+
+/// Penalty for every character of the query that isn't matched at all
+const TYPO: i64 = 24;
+
+const NONE: i64 = i64::MIN / 2;
+
+/// Score how well `text` matches `query`, or None if it doesn't match at all.
+///
+/// The characters of the query have to appear in the text in the same order (ignoring
+/// case), but not necessarily next to each other. Matches at the start of words, in the
+/// file name and in one piece score higher. To be forgiving about typos, one in every
+/// four characters of the query may be missing from the text, at a cost.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let query = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect::<Vec<_>>();
+    let original = text.chars().collect::<Vec<_>>();
+    let text = original
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect::<Vec<_>>();
+    if query.is_empty() {
+        return Some(0);
+    }
+
+    let file_name_start = original
+        .iter()
+        .rposition(|&c| c == '/')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let bonus = |pos: usize| {
+        let mut bonus = MATCH;
+        let word_start = pos == 0
+            || matches!(original[pos - 1], '/' | '_' | '-' | '.' | ' ')
+            || (original[pos].is_uppercase() && original[pos - 1].is_lowercase());
+        if word_start {
+            bonus += WORD_START;
+        }
+        if pos >= file_name_start {
+            bonus += FILE_NAME;
+        }
+        bonus
+    };
+
+    let max_typos = query.len() / 4;
+    // best[typos][0] is the score before anything matched, and best[typos][pos + 1]
+    // the best score with the last matched character at pos
+    let mut best = vec![vec![NONE; text.len() + 1]; max_typos + 1];
+    best[0][0] = 0;
+
+    for &c in query.iter() {
+        let mut next = vec![vec![NONE; text.len() + 1]; max_typos + 1];
+        for typos in 0..=max_typos {
+            let prev = &best[typos];
+            // best of prev[i] + i, for the i that are at least two characters back
+            let mut gapped = NONE;
+            for i in 1..=text.len() {
+                if i >= 3 && prev[i - 2] > NONE {
+                    gapped = gapped.max(prev[i - 2] + (i - 2) as i64 * GAP);
+                }
+
+                let mut score = NONE;
+                if text[i - 1] == c {
+                    let bonus = bonus(i - 1);
+                    // nothing is lost for skipping characters before the first match
+                    if prev[0] > NONE {
+                        score = score.max(prev[0] + bonus);
+                    }
+                    if i >= 2 && prev[i - 1] > NONE {
+                        score = score.max(prev[i - 1] + bonus + CONSECUTIVE);
+                    }
+                    if gapped > NONE {
+                        score = score.max(gapped - (i - 1) as i64 * GAP + bonus);
+                    }
+                }
+                next[typos][i] = score;
+            }
+
+            // or this character of the query is a typo, and it's skipped
+            if typos > 0 {
+                for i in 0..=text.len() {
+                    let skipped = best[typos - 1][i];
+                    if skipped > NONE {
+                        next[typos][i] = next[typos][i].max(skipped - TYPO);
+                    }
+                }
+            }
+        }
+        best = next;
+    }
+
+    best.iter()
+        .flat_map(|scores| scores[1..].iter())
+        .copied()
+        .filter(|&score| score > NONE)
+        .max()
+}
+
+/// Rank `texts` by how well they match `query`, best first.
+///
+/// Returns the indices of the texts that match at all. Equally good matches keep their
+/// order.
+pub fn rank<'a>(query: &str, texts: impl IntoIterator<Item = &'a str>) -> Vec<usize> {
+    let mut matches = texts
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, text)| score(query, text).map(|score| (i, score)))
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|&(_, score)| Reverse(score));
+    matches.into_iter().map(|(i, _)| i).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rank, score};
+
+    #[test]
+    fn matches_in_order_ignoring_case() {
+        assert!(score("rprt", "/home/user/report.txt").is_some());
+        assert!(score("README", "/src/readme.md").is_some());
+        assert!(score("tropre", "/home/user/report.txt").is_none());
+        assert_eq!(score("", "/anything"), Some(0));
+    }
+
+    #[test]
+    fn tolerates_one_typo_in_four_characters() {
+        assert!(score("abcd", "/tmp/abcd").is_some());
+        assert!(score("abxd", "/tmp/abcd").is_some());
+        assert!(score("axxd", "/tmp/abcd").is_none());
+        assert!(score("repxort", "/tmp/report").is_some());
+        assert!(score("rxepxort", "/tmp/report").is_some());
+        assert!(score("rxexpxort", "/tmp/report").is_none());
+    }
+
+    #[test]
+    fn typos_cost_something() {
+        assert!(score("abxd", "/tmp/abcd") < score("abcd", "/tmp/abcd"));
+    }
+
+    #[test]
+    fn file_name_outranks_directory() {
+        let paths = ["/home/user/notes/todo.txt", "/home/user/old/notes.txt"];
+        assert_eq!(rank("notes", paths.iter().copied()), vec![1, 0]);
+    }
+
+    #[test]
+    fn word_starts_outrank_the_middle_of_words() {
+        let paths = ["/tmp/afxb", "/tmp/foo_bar"];
+        assert_eq!(rank("fb", paths.iter().copied()), vec![1, 0]);
+    }
+
+    #[test]
+    fn ranking_is_stable() {
+        let paths = ["/a/report", "/b/other", "/c/report", "/d/report"];
+        assert_eq!(rank("report", paths.iter().copied()), vec![0, 2, 3]);
+    }
+}
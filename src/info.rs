@@ -1,23 +1,92 @@
+use std::ffi::OsString;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
-use chrono::{DateTime, Local, TimeZone};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use percent_encoding::{percent_decode, percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
+use crate::directorysizes::{apparent_size, disk_usage};
 use crate::errors::{Error, TrashInfoError};
+use crate::TrashDir;
 
 const DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
 
+/// Bytes that get escaped in the `Path` key.
+///
+/// The spec asks for the value to be escaped like a URL (RFC 2396), so everything
+/// except the "unreserved" characters and the `/` separator is percent-encoded.
+const PATH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'!')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')');
+
 fn parse_key_value(line: &str) -> Option<(&str, &str)> {
-    let mut parts = line.split('=').peekable();
-    let key = if let Some(key) = parts.next() {
-        key
-    } else {
-        return None;
-    };
-
-    let value = &line[key.len() + 1..];
-    Some((key, value))
+    line.split_once('=')
+}
+
+/// Percent-encode the raw bytes of a path so it can be stored in a .trashinfo file.
+pub(crate) fn encode_path(path: &Path) -> String {
+    percent_encode(path.as_os_str().as_bytes(), PATH_ENCODE_SET).to_string()
+}
+
+/// Decode a percent-encoded `Path` value back into a path.
+///
+/// The decoded bytes don't have to be valid UTF-8.
+pub(crate) fn decode_path(value: &str) -> PathBuf {
+    let bytes = percent_decode(value.as_bytes()).collect::<Vec<_>>();
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+/// The kind of file that was deleted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileKind {
+    /// A regular file
+    File,
+
+    /// A directory
+    Directory,
+
+    /// A symbolic link
+    Symlink,
+
+    /// Anything else, like a FIFO or a device node
+    Other,
+}
+
+impl FileKind {
+    /// A short lowercase name for this kind of file.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Directory => "directory",
+            FileKind::Symlink => "symlink",
+            FileKind::Other => "other",
+        }
+    }
+}
+
+impl FromStr for FileKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "file" => Ok(FileKind::File),
+            "directory" | "dir" => Ok(FileKind::Directory),
+            "symlink" => Ok(FileKind::Symlink),
+            "other" => Ok(FileKind::Other),
+            _ => Err(Error::UnknownFileKind(s.to_owned())),
+        }
+    }
 }
 
 /// .trashinfo Data
@@ -34,15 +103,28 @@ pub struct TrashInfo {
 
     /// The location of the `info` description file.
     pub info_path: PathBuf,
+
+    /// The top directory of the mount this entry's trash directory belongs to.
+    ///
+    /// This is None for the home trash. Otherwise, `path` is written relative to it.
+    pub topdir: Option<PathBuf>,
+
+    /// How much space the deleted directory takes up on disk, as recorded in the
+    /// trash's `directorysizes` file.
+    ///
+    /// This is None if there's no (up to date) entry for it.
+    pub cached_size: Option<u64>,
 }
 
 impl TrashInfo {
     /// Create a new TrashInfo based on the .trashinfo path and the deleted file path
     ///
-    /// This is useful for reading files from the Trash.
+    /// This is useful for reading files from the Trash. If `topdir` is given, relative
+    /// paths in the .trashinfo file are resolved against it.
     pub fn from_files(
         info_path: impl AsRef<Path>,
         deleted_path: impl AsRef<Path>,
+        topdir: Option<&Path>,
     ) -> Result<Self, Error> {
         let info_path = info_path.as_ref().to_path_buf();
         let deleted_path = deleted_path.as_ref().to_path_buf();
@@ -67,11 +149,15 @@ impl TrashInfo {
             if let Some((key, value)) = parse_key_value(&line) {
                 match key {
                     "Path" => {
-                        let value = PathBuf::from(value);
+                        let value = decode_path(value);
                         path = Some(value)
                     }
                     "DeletionDate" => {
-                        let date = Local.datetime_from_str(value, DATE_FORMAT)?;
+                        let date = NaiveDateTime::parse_from_str(value, DATE_FORMAT)?;
+                        let date = Local
+                            .from_local_datetime(&date)
+                            .earliest()
+                            .ok_or_else(|| Error::InvalidDate(value.to_owned()))?;
                         deletion_date = Some(date)
                     }
                     _ => continue,
@@ -81,9 +167,10 @@ impl TrashInfo {
             }
         }
 
-        let path = match path {
-            Some(path) => path,
-            None => return Err(Error::BadTrashInfo(TrashInfoError::MissingPath)),
+        let path = match (path, topdir) {
+            (Some(path), Some(topdir)) if path.is_relative() => topdir.join(path),
+            (Some(path), _) => path,
+            (None, _) => return Err(Error::BadTrashInfo(TrashInfoError::MissingPath)),
         };
 
         let deletion_date = match deletion_date {
@@ -96,13 +183,62 @@ impl TrashInfo {
             deletion_date,
             deleted_path,
             info_path,
+            topdir: topdir.map(Path::to_path_buf),
+            cached_size: None,
+        })
+    }
+
+    /// Get the trash directory this entry belongs to.
+    pub fn trash_dir(&self) -> Option<TrashDir> {
+        self.info_path.parent()?.parent().map(TrashDir::from)
+    }
+
+    /// Get the kind of the deleted file.
+    pub fn file_kind(&self) -> Result<FileKind, Error> {
+        let file_type = self.deleted_path.symlink_metadata()?.file_type();
+        Ok(if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Directory
+        } else if file_type.is_file() {
+            FileKind::File
+        } else {
+            FileKind::Other
         })
     }
 
+    /// Get the size of the deleted file in bytes.
+    ///
+    /// For directories, this adds up the sizes of their contents.
+    pub fn size(&self) -> Result<u64, Error> {
+        let meta = self.deleted_path.symlink_metadata()?;
+        if meta.is_dir() {
+            apparent_size(&self.deleted_path)
+        } else {
+            Ok(meta.len())
+        }
+    }
+
+    /// Get how much space the deleted file takes up on disk, in bytes.
+    ///
+    /// For directories, the size from the `directorysizes` cache is used if there is
+    /// one. Otherwise, the directory is walked to add up its contents.
+    pub fn disk_usage(&self) -> Result<u64, Error> {
+        match self.cached_size {
+            Some(size) => Ok(size),
+            None => disk_usage(&self.deleted_path),
+        }
+    }
+
     /// Write the current TrashInfo into a .trashinfo file.
     pub fn write(&self, mut out: impl Write) -> Result<(), io::Error> {
         writeln!(out, "[Trash Info]")?;
-        writeln!(out, "Path={}", self.path.to_str().unwrap())?;
+        let path = self
+            .topdir
+            .as_ref()
+            .and_then(|topdir| self.path.strip_prefix(topdir).ok())
+            .unwrap_or(&self.path);
+        writeln!(out, "Path={}", encode_path(path))?;
         writeln!(
             out,
             "DeletionDate={}",
@@ -111,3 +247,39 @@ impl TrashInfo {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::{decode_path, encode_path};
+
+    #[test]
+    fn encode_escapes_special_characters() {
+        assert_eq!(encode_path(Path::new("/tmp/a b")), "/tmp/a%20b");
+        assert_eq!(encode_path(Path::new("/tmp/100%")), "/tmp/100%25");
+        assert_eq!(encode_path(Path::new("/tmp/a\nb")), "/tmp/a%0Ab");
+        assert_eq!(
+            encode_path(Path::new(OsStr::from_bytes(b"/tmp/\xffname"))),
+            "/tmp/%FFname"
+        );
+    }
+
+    #[test]
+    fn decode_reverses_encode() {
+        let paths: &[&[u8]] = &[
+            b"/home/user/file.txt",
+            b"/tmp/a\nb",
+            b"/tmp/100%",
+            b"/tmp/%41",
+            b"/tmp/\xff\xfename",
+            "/tmp/caf\u{e9}".as_bytes(),
+        ];
+        for path in paths {
+            let path = Path::new(OsStr::from_bytes(path));
+            assert_eq!(decode_path(&encode_path(path)), path);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! A journal of what `put` moved into the trash, so that it can be undone.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use chrono::{DateTime, Local};
+
+use crate::info::{decode_path, encode_path};
+use crate::Error;
+use crate::{TrashDir, TrashInfo, XDG};
+
+/// Only this many of the most recent batches are remembered
+const MAX_BATCHES: usize = 100;
+
+/// Everything that was trashed by a single `put`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Batch {
+    /// Identifies the batch, made from the time and the id of the process
+    pub id: String,
+
+    /// When the batch was trashed, in RFC 3339 format
+    pub date: String,
+
+    /// The entries that were created in the trash
+    pub entries: Vec<JournalEntry>,
+}
+
+/// A single entry that was created in the trash
+///
+/// Paths are percent-encoded like in .trashinfo files, so that they survive the
+/// trip through JSON even if they aren't valid UTF-8.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The trash directory the entry was created in
+    trash_dir: String,
+
+    /// The name of the entry in `$trash/files`
+    name: String,
+
+    /// Where the file was trashed from
+    path: String,
+}
+
+impl Batch {
+    /// Start a new, empty batch.
+    pub fn new() -> Self {
+        let now = Local::now();
+        Batch {
+            id: format!("{}-{}", now.format("%Y%m%d-%H%M%S"), process::id()),
+            date: now.to_rfc3339(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Record an entry that was just created in the trash.
+    pub fn push(&mut self, info: &TrashInfo) {
+        let (trash_dir, name) = match (info.trash_dir(), info.deleted_path.file_name()) {
+            (Some(trash_dir), Some(name)) => (trash_dir, name),
+            _ => return,
+        };
+        self.entries.push(JournalEntry {
+            trash_dir: encode_path(trash_dir.path()),
+            name: encode_path(Path::new(name)),
+            path: encode_path(&info.path),
+        });
+    }
+
+    /// When the batch was trashed, if the date can be read.
+    pub fn date(&self) -> Option<DateTime<Local>> {
+        DateTime::parse_from_rfc3339(&self.date)
+            .ok()
+            .map(|date| date.with_timezone(&Local))
+    }
+}
+
+impl JournalEntry {
+    /// Where the file was trashed from.
+    pub fn path(&self) -> PathBuf {
+        decode_path(&self.path)
+    }
+
+    /// Look the entry up in its trash directory.
+    ///
+    /// Returns None if it isn't in the trash anymore, or if its name has been taken by
+    /// something else that was trashed since.
+    pub fn find(&self) -> Result<Option<TrashInfo>, Error> {
+        let trash_dir = TrashDir::from(decode_path(&self.trash_dir));
+        let name = decode_path(&self.name).into_os_string();
+        let mut info_name = name.clone();
+        info_name.push(".trashinfo");
+
+        let info_path = trash_dir.path().join("info").join(info_name);
+        let deleted_path = trash_dir.path().join("files").join(name);
+        if !info_path.exists() || deleted_path.symlink_metadata().is_err() {
+            return Ok(None);
+        }
+
+        let topdir = trash_dir.topdir();
+        let info = TrashInfo::from_files(info_path, deleted_path, topdir.as_deref())?;
+        if info.path != self.path() {
+            return Ok(None);
+        }
+        Ok(Some(info))
+    }
+}
+
+/// The location of the journal, `$XDG_DATA_HOME/garbage/journal`.
+fn journal_path() -> PathBuf {
+    XDG.get_data_home().join("garbage").join("journal")
+}
+
+/// Read every batch in the journal, oldest first.
+///
+/// A missing journal is treated as empty, and lines that can't be read are skipped.
+pub fn read() -> Result<Vec<Batch>, Error> {
+    let file = match File::open(journal_path()) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::from(err)),
+    };
+
+    let mut batches = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(batch) = serde_json::from_str(&line?) {
+            batches.push(batch);
+        }
+    }
+    Ok(batches)
+}
+
+/// Add a batch to the end of the journal.
+///
+/// Once the journal holds too many batches, the oldest ones are forgotten.
+pub fn append(batch: &Batch) -> Result<(), Error> {
+    let mut batches = read()?;
+    if batches.len() >= MAX_BATCHES {
+        batches.drain(..=batches.len() - MAX_BATCHES);
+        batches.push(batch.clone());
+        return write(&batches);
+    }
+
+    let path = journal_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(batch)?;
+    line.push('\n');
+    // a single write, so that lines from puts running at the same time don't mix
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Replace the whole journal with these batches.
+///
+/// The journal is written to a temporary file and then atomically renamed over the
+/// old one.
+pub fn write(batches: &[Batch]) -> Result<(), Error> {
+    let path = journal_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_file_name(format!("journal.{}", process::id()));
+
+    {
+        let mut out = File::create(&temp)?;
+        for batch in batches.iter() {
+            let line = serde_json::to_string(batch)?;
+            writeln!(out, "{}", line)?;
+        }
+        out.sync_all()?;
+    }
+
+    fs::rename(&temp, &path)?;
+    Ok(())
+}
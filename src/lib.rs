@@ -10,22 +10,37 @@ extern crate log;
 #[macro_use]
 extern crate anyhow;
 #[macro_use]
+extern crate serde;
+#[macro_use]
 extern crate thiserror;
 
+mod check;
+mod copy;
 mod dir;
+mod directorysizes;
 mod errors;
+mod filter;
+mod fuzzy;
 mod info;
+mod journal;
 mod mounts;
 pub mod ops;
+mod protect;
+mod term;
+pub mod timespec;
 mod utils;
+mod walk;
 
 use std::path::PathBuf;
 
 use xdg::BaseDirectories;
 
+pub use crate::check::Problem;
 pub use crate::dir::TrashDir;
+pub use crate::directorysizes::DirectorySizes;
 pub use crate::errors::Error;
-pub use crate::info::TrashInfo;
+pub use crate::filter::{Filter, SortKey};
+pub use crate::info::{FileKind, TrashInfo};
 use crate::mounts::Mounts;
 
 lazy_static! {
@@ -3,9 +3,13 @@
 extern crate anyhow;
 
 use std::alloc::System;
+use std::process;
 
 use anyhow::Result;
-use garbage::ops::{self, EmptyOptions, ListOptions, PutOptions, RestoreOptions};
+use garbage::ops::{
+    self, CheckOptions, EmptyOptions, ListOptions, PutOptions, RestoreOptions, StatsOptions,
+    TuiOptions, UndoOptions,
+};
 use structopt::StructOpt;
 
 #[global_allocator]
@@ -13,6 +17,13 @@ static A: System = System;
 
 #[derive(StructOpt)]
 enum Command {
+    /// Check trash directories for inconsistencies.
+    ///
+    /// This finds files without a .trashinfo file, .trashinfo files without
+    /// a file, and .trashinfo files that can't be read. Pass --repair to fix them.
+    #[structopt(name = "check")]
+    Check(CheckOptions),
+
     /// Empty a trash directory.
     #[structopt(name = "empty")]
     Empty(EmptyOptions),
@@ -26,21 +37,52 @@ enum Command {
     /// If a trash directory isn't specified, the best strategy is picked
     /// for each file that's deleted (after shell glob expansion). The algorithm
     /// for deciding a strategy is specified in the FreeDesktop Trash spec.
+    ///
+    /// Some paths are never trashed: the root of the filesystem, your home
+    /// directory, mount points, trash directories and the directories they're in,
+    /// and anything matching one of the globs listed in
+    /// $XDG_CONFIG_HOME/garbage/protected (one per line, like ~/thesis/**).
     #[structopt(name = "put")]
     Put(PutOptions),
 
     /// Restores files from the trash.
     #[structopt(name = "restore")]
     Restore(RestoreOptions),
+
+    /// Shows how much space the trash takes up.
+    ///
+    /// For every trash directory, this prints the number of files, their total size,
+    /// the oldest and newest files, the largest files, and how much came from
+    /// each directory.
+    #[structopt(name = "stats", alias = "du")]
+    Stats(StatsOptions),
+
+    /// Browse the trash in a full-screen interface.
+    ///
+    /// Type / to search, space to select files, enter to restore them,
+    /// t to restore them somewhere else and D to delete them for good.
+    #[structopt(name = "tui")]
+    Tui(TuiOptions),
+
+    /// Undo a put, restoring everything it moved into the trash.
+    ///
+    /// Every put is recorded in a journal ($XDG_DATA_HOME/garbage/journal), and
+    /// the most recent one is undone unless the id of another one is given.
+    #[structopt(name = "undo")]
+    Undo(UndoOptions),
 }
 
 fn run() -> Result<()> {
     let cmd = Command::from_args();
     match cmd {
+        Command::Check(options) => ops::check(options),
         Command::Empty(options) => ops::empty(options),
         Command::List(options) => ops::list(options),
         Command::Put(options) => ops::put(options),
         Command::Restore(options) => ops::restore(options),
+        Command::Stats(options) => ops::stats(options),
+        Command::Tui(options) => ops::tui(options),
+        Command::Undo(options) => ops::undo(options),
     }
 }
 
@@ -52,6 +94,7 @@ fn main() {
             // for cause in err.chain() {
             //     eprintln!("- {:?}", cause);
             // }
+            process::exit(1);
         }
     }
 }
@@ -1,15 +1,19 @@
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::fs::File;
 use std::io::Read;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 
 use anyhow::Error;
 use libmount::mountinfo::Parser;
 
 use crate::utils;
+use crate::TrashDir;
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct MountPoint {
     pub mount_id: u64,
     pub parent_id: u64,
@@ -73,4 +77,89 @@ impl Mounts {
             .max_by_key(|mp| mp.mount_point.components().count())
             .map(|mp| mp.mount_point.to_path_buf())
     }
+
+    /// Check whether a filesystem is mounted on this exact path.
+    pub fn is_mount_point(&self, path: impl AsRef<Path>) -> bool {
+        let path = path.as_ref();
+        self.0.iter().any(|mp| mp.mount_point == path)
+    }
+
+    /// Find a filesystem that's mounted somewhere inside of path (but not on path
+    /// itself).
+    pub fn find_mount_below(&self, path: impl AsRef<Path>) -> Option<PathBuf> {
+        let path = path.as_ref().canonicalize().ok()?;
+
+        self.0
+            .iter()
+            .find(|mp| mp.mount_point != path && mp.mount_point.starts_with(&path))
+            .map(|mp| mp.mount_point.to_path_buf())
+    }
+
+    /// Find every trash directory the current user has, across all mounts.
+    ///
+    /// The home trash always comes first. After that, each mount point is checked for
+    /// a valid `$topdir/.Trash/$uid` or `$topdir/.Trash-$uid` directory. Nothing is
+    /// created here, and a directory that's visible through several mounts (such as
+    /// a bind mount) is only returned once.
+    pub fn trash_dirs(&self) -> Vec<TrashDir> {
+        let uid = utils::get_uid();
+        let mut seen = HashSet::new();
+        let mut trash_dirs = Vec::new();
+
+        let mut candidates = vec![TrashDir::get_home_trash()];
+        for mp in self.0.iter() {
+            let topdir = &mp.mount_point;
+            if should_use_topdir_trash(topdir) {
+                candidates.push(TrashDir::from(topdir.join(".Trash").join(uid.to_string())));
+            }
+            candidates.push(TrashDir::from(topdir.join(format!(".Trash-{}", uid))));
+        }
+
+        for (i, trash_dir) in candidates.into_iter().enumerate() {
+            let meta = match trash_dir.path().symlink_metadata() {
+                Ok(meta) if meta.is_dir() => meta,
+                // the home trash is always valid, even before it's been created
+                _ if i == 0 => {
+                    trash_dirs.push(trash_dir);
+                    continue;
+                }
+                _ => continue,
+            };
+            if seen.insert((meta.dev(), meta.ino())) {
+                trash_dirs.push(trash_dir);
+            }
+        }
+
+        trash_dirs
+    }
+}
+
+/// Can we use $topdir/.Trash?
+///
+/// 1. If it doesn't exist, don't create it.
+/// 2. All users should be able to write to it
+/// 3. It must have sticky-bit permissions if the filesystem supports it.
+/// 4. The directory must not be a symbolic link.
+pub fn should_use_topdir_trash(mount: impl AsRef<Path>) -> bool {
+    let mount = mount.as_ref();
+    let trash_dir = mount.join(".Trash");
+
+    if !trash_dir.exists() {
+        return false;
+    }
+
+    let dir = match File::open(trash_dir) {
+        Ok(file) => file,
+        Err(_) => return false,
+    };
+    let meta = match dir.metadata() {
+        Ok(meta) => meta,
+        Err(_) => return false,
+    };
+    if meta.file_type().is_symlink() {
+        return false;
+    }
+    let perms = meta.permissions();
+
+    perms.mode() & 0o1000 > 0
 }
@@ -0,0 +1,74 @@
+use anyhow::Result;
+
+use crate::ops::TrashDirOptions;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Found {0} problem(s), run with --repair to fix them")]
+    ProblemsFound(usize),
+
+    #[error("Failed to repair {0} problem(s)")]
+    Failed(usize),
+}
+
+/// Options to pass to check
+#[derive(StructOpt)]
+pub struct CheckOptions {
+    /// Fix the problems that are found
+    #[structopt(long = "repair")]
+    repair: bool,
+
+    /// Only print what would be done to repair the problems,
+    /// without actually changing anything.
+    #[structopt(long = "dry")]
+    dry: bool,
+
+    #[structopt(flatten)]
+    trash_dir: TrashDirOptions,
+}
+
+/// Check trash directories for inconsistencies, and optionally repair them
+pub fn check(options: CheckOptions) -> Result<()> {
+    let trash_dirs = options.trash_dir.trash_dirs();
+
+    let mut found = 0;
+    let mut failed = 0;
+    for trash_dir in trash_dirs {
+        let problems = match trash_dir.check() {
+            Ok(problems) => problems,
+            Err(err) => {
+                eprintln!(
+                    "failed to check trash directory {}: {}",
+                    trash_dir.path().display(),
+                    err
+                );
+                failed += 1;
+                continue;
+            }
+        };
+
+        for problem in problems {
+            found += 1;
+            println!("{}", problem);
+
+            if options.dry {
+                println!("  would {}", problem.repair_action());
+            } else if options.repair {
+                match problem.repair(&trash_dir) {
+                    Ok(()) => println!("  repaired: {}", problem.repair_action()),
+                    Err(err) => {
+                        eprintln!("  failed to {}: {}", problem.repair_action(), err);
+                        failed += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    ensure!(failed == 0, Error::Failed(failed));
+    ensure!(
+        found == 0 || (options.repair && !options.dry),
+        Error::ProblemsFound(found)
+    );
+    Ok(())
+}
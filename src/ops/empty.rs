@@ -1,63 +1,263 @@
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Write};
 
 use anyhow::Result;
 use chrono::{Duration, Local};
 
-use crate::TrashDir;
+use crate::ops::select::{parse_selection, pattern_filter};
+use crate::ops::{read_entries, FilterOptions, ThreadOptions, TrashDirOptions};
+use crate::utils;
+use crate::{DirectorySizes, Filter, TrashDir, TrashInfo};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Failed to delete {0} of {1} file(s)")]
+    Failed(usize, usize),
+}
 
 /// Options to pass to empty
 #[derive(StructOpt)]
 pub struct EmptyOptions {
+    /// Only delete files whose original path matches one of these globs.
+    /// Patterns without a '/' are matched against the file name only,
+    /// so `garbage empty '*.o'` deletes every trashed object file
+    patterns: Vec<String>,
+
     /// Only list the files that are to be deleted, without
     /// actually deleting anything.
     #[structopt(long = "dry")]
     pub dry: bool,
 
     /// Delete all files older than (this number) of integer days.
-    /// Removes everything if this option is not specified
+    /// This is the same as --older-than with a number of days
     #[structopt(long = "days")]
     days: Option<u32>,
 
-    /// The path to the trash directory to empty.
-    /// By default, this is your home directory's trash ($XDG_DATA_HOME/Trash)
-    #[structopt(long = "trash-dir", parse(from_os_str))]
-    trash_dir: Option<PathBuf>,
+    // removes everything if none of these are specified
+    #[structopt(flatten)]
+    filter: FilterOptions,
+
+    /// Only delete the file with this index, as shown by list --show-index (can be
+    /// given multiple times)
+    #[structopt(long = "index", conflicts_with_all = &["max-size", "keep-free"])]
+    indices: Vec<usize>,
+
+    /// Pick the files to delete from a numbered list
+    #[structopt(long = "interactive", short = "i", conflicts_with_all = &["max-size", "keep-free"])]
+    interactive: bool,
+
+    /// Only delete as many files as needed (oldest first) to bring the
+    /// space the trash takes up on disk down to this size (like 500M or 20GiB)
+    #[structopt(long = "max-size", parse(try_from_str = utils::parse_size))]
+    max_size: Option<u64>,
+
+    /// Only delete as many files as needed (oldest first) for the filesystem
+    /// holding each trash directory to have this much free space (like 500M or 20GiB)
+    #[structopt(long = "keep-free", parse(try_from_str = utils::parse_size))]
+    keep_free: Option<u64>,
+
+    #[structopt(flatten)]
+    trash_dir: TrashDirOptions,
+
+    #[structopt(flatten)]
+    threads: ThreadOptions,
 }
 
 /// Actually delete files in the trash.
 pub fn empty(options: EmptyOptions) -> Result<()> {
-    let trash_dir = TrashDir::from_opt(options.trash_dir);
+    options.threads.apply();
+    let trash_dirs = options.trash_dir.trash_dirs();
+
+    let mut filters = options.filter.into_filters()?;
+    if !options.patterns.is_empty() {
+        let patterns = options
+            .patterns
+            .iter()
+            .map(|pattern| pattern_filter(pattern))
+            .collect::<Result<Vec<_>>>()?;
+        filters.push(Filter::Any(patterns));
+    }
+    if let Some(days) = options.days {
+        filters.push(Filter::DeletedBefore(
+            Local::now() - Duration::days(days.into()),
+        ));
+    }
+
+    // oldest first
+    let mut files = read_entries(&trash_dirs);
+    if !options.indices.is_empty() {
+        let indices = options.indices.iter().collect::<BTreeSet<_>>();
+        if let Some(i) = indices.iter().find(|&&&i| i >= files.len()) {
+            bail!(
+                "Invalid index {}, there are only {} files in the trash",
+                i,
+                files.len()
+            );
+        }
+        files = files
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| indices.contains(i))
+            .map(|(_, info)| info)
+            .collect();
+    }
 
-    // cutoff date
-    let cutoff = if let Some(days) = options.days {
-        Local::now() - Duration::days(days.into())
+    let mut files = if options.max_size.is_some() || options.keep_free.is_some() {
+        pick_for_budget(files, &filters, options.max_size, options.keep_free)?
     } else {
-        Local::now()
+        files
+            .into_iter()
+            .filter(|info| Filter::matches_all(&filters, info))
+            .collect()
     };
 
-    for file in trash_dir.iter()? {
-        let file = file?;
+    if options.interactive {
+        files = prompt_selection(files)?;
+    }
 
-        // ignore files that were deleted after the cutoff (younger)
-        let ignore = file.deletion_date > cutoff;
+    if options.dry {
+        for file in files {
+            println!("{:?}", file.path);
+        }
+        return Ok(());
+    }
 
-        if !ignore {
-            if options.dry {
-                println!("{:?}", file.path);
-            } else {
-                fs::remove_file(file.info_path)?;
+    let total = files.len();
+    let mut failed = Vec::new();
+    for file in files {
+        if let Err(err) = delete_entry(&file) {
+            eprintln!("failed to delete {}: {}", file.path.display(), err);
+            failed.push(file.path);
+        }
+    }
 
-                if file.deleted_path.exists() {
-                    if file.deleted_path.is_dir() {
-                        fs::remove_dir_all(file.deleted_path)?;
-                    } else {
-                        fs::remove_file(file.deleted_path)?;
-                    }
+    for err in prune_directory_sizes(&trash_dirs) {
+        eprintln!("{}", err);
+    }
+
+    if !failed.is_empty() {
+        eprintln!("These files could not be deleted and are still in the trash:");
+        for path in failed.iter() {
+            eprintln!("- {}", path.display());
+        }
+        bail!(Error::Failed(failed.len(), total));
+    }
+
+    Ok(())
+}
+
+/// Permanently delete a single entry from the trash.
+///
+/// The file itself is deleted first, so if that fails, the .trashinfo file is still
+/// around and the entry can be retried later instead of becoming an orphan.
+pub(super) fn delete_entry(info: &TrashInfo) -> Result<()> {
+    if info.deleted_path.symlink_metadata().is_ok() {
+        utils::force_remove_all(&info.deleted_path)?;
+    }
+    fs::remove_file(&info.info_path)?;
+    Ok(())
+}
+
+/// Drop the `directorysizes` entries of everything that's no longer in these trash
+/// directories, and return what went wrong.
+pub(super) fn prune_directory_sizes(trash_dirs: &[TrashDir]) -> Vec<String> {
+    let mut errors = Vec::new();
+    for trash_dir in trash_dirs.iter() {
+        if let Ok(Some(_)) = trash_dir.check_info_dir() {
+            // writing the cache back leaves out the entries that are gone
+            if let Err(err) = DirectorySizes::update(trash_dir, |_| Ok(())) {
+                errors.push(format!(
+                    "failed to update directorysizes of {}: {}",
+                    trash_dir.path().display(),
+                    err
+                ));
+            }
+        }
+    }
+    errors
+}
+
+/// Pick the oldest files that match the filters until the trash fits in the budget.
+///
+/// The total size counts every file in the trash, even the ones that don't match the
+/// filters. Free space is checked separately for every trash directory.
+fn pick_for_budget(
+    files: Vec<TrashInfo>,
+    filters: &[Filter],
+    max_size: Option<u64>,
+    keep_free: Option<u64>,
+) -> Result<Vec<TrashInfo>> {
+    let files = files
+        .into_iter()
+        .map(|info| {
+            let size = info.disk_usage().unwrap_or(0);
+            (info, size)
+        })
+        .collect::<Vec<_>>();
+    let mut total = files.iter().map(|(_, size)| size).sum::<u64>();
+
+    let mut free = HashMap::new();
+    let mut picked = Vec::new();
+    for (info, size) in files {
+        if !Filter::matches_all(filters, &info) {
+            continue;
+        }
+
+        let trash_path = info
+            .trash_dir()
+            .map(|trash_dir| trash_dir.path().to_path_buf())
+            .unwrap_or_default();
+
+        let too_big = matches!(max_size, Some(max_size) if total > max_size);
+        let too_full = match keep_free {
+            Some(keep_free) => {
+                if !free.contains_key(&trash_path) {
+                    free.insert(trash_path.clone(), utils::free_space(&trash_path)?);
                 }
+                free[&trash_path] < keep_free
             }
+            None => false,
+        };
+        if !too_big && !too_full {
+            continue;
+        }
+
+        // pretend the space is already freed up, so this also works for --dry
+        total -= size;
+        if let Some(free) = free.get_mut(&trash_path) {
+            *free += size;
         }
+        picked.push(info);
     }
 
-    Ok(())
+    Ok(picked)
+}
+
+/// Print a numbered list of files and ask which ones to delete.
+fn prompt_selection(files: Vec<TrashInfo>) -> Result<Vec<TrashInfo>> {
+    if files.is_empty() {
+        return Ok(files);
+    }
+
+    for (i, info) in files.iter().enumerate() {
+        println!("[{}]\t{}\t{}", i, info.deletion_date, info.path.display());
+    }
+    eprint!(
+        "which files to delete? (like '0 2 4-6', or 'all') [0..{}] ",
+        files.len() - 1
+    );
+    io::stderr().flush()?;
+
+    let stdin = io::stdin();
+    let mut s = String::new();
+    stdin.read_line(&mut s)?;
+
+    let selected = parse_selection(&s, files.len())?;
+    Ok(files
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| selected.binary_search(i).is_ok())
+        .map(|(_, info)| info)
+        .collect())
 }
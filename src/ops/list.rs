@@ -1,37 +1,210 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::io::{self, BufWriter, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::str::FromStr;
 
 use anyhow::Result;
 
-use crate::TrashDir;
+use crate::info::encode_path;
+use crate::ops::{read_entries, FilterOptions, TrashDirOptions};
+use crate::{Filter, SortKey, TrashDir, TrashInfo};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Unknown format '{0}', expected one of: plain, json, ndjson, csv, tsv")]
+    UnknownFormat(String),
+}
+
+/// How the listing is printed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Deletion date and original path (and the index with --show-index), separated
+    /// by tabs
+    Plain,
+
+    /// A single JSON array of objects
+    Json,
+
+    /// One JSON object per line
+    Ndjson,
+
+    /// Comma-separated values, with a header row
+    Csv,
+
+    /// Tab-separated values, with a header row
+    Tsv,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Format::Plain),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            _ => Err(Error::UnknownFormat(s.to_owned())),
+        }
+    }
+}
 
 /// Options to pass to list
 #[derive(StructOpt)]
 pub struct ListOptions {
-    /// The path to the trash directory to list.
-    /// By default, this is your home directory's trash ($XDG_DATA_HOME/Trash)
-    #[structopt(long = "trash-dir", parse(from_os_str))]
-    trash_dir: Option<PathBuf>,
+    #[structopt(flatten)]
+    trash_dir: TrashDirOptions,
+
+    /// The output format: plain, json, ndjson, csv or tsv
+    /// (in all but plain, the paths of an entry are percent-encoded if any of
+    /// them isn't valid UTF-8, and its "encoded" field is set)
+    #[structopt(long = "format", default_value = "plain")]
+    format: Format,
+
+    /// End every entry with a NUL byte instead of a newline (ignored for json).
+    /// In the plain format, paths are printed exactly as they are on disk
+    #[structopt(long = "null", short = "0")]
+    null: bool,
+
+    #[structopt(flatten)]
+    filter: FilterOptions,
+
+    /// What to sort by: date, path, size or name
+    #[structopt(long = "sort", default_value = "date")]
+    sort: SortKey,
+
+    /// Reverse the sort order
+    #[structopt(long = "reverse", short = "r")]
+    reverse: bool,
+
+    /// Only list this many files
+    #[structopt(long = "limit", short = "n")]
+    limit: Option<usize>,
+
+    /// Start every line of the plain format with the index of the file, which is
+    /// what restore --index and empty --index take (the other formats always
+    /// have an "index" field). Indices don't change with --sort or the filters, but
+    /// do with --trash-dir
+    #[structopt(long = "show-index", short = "i")]
+    show_index: bool,
+}
+
+/// A single entry in the machine-readable formats
+///
+/// If any of the paths of an entry isn't valid UTF-8, all of them are percent-encoded
+/// like in .trashinfo files, and `encoded` is set, so that they still point to the
+/// right files.
+#[derive(Serialize)]
+struct Record {
+    index: usize,
+    path: String,
+    deleted_path: String,
+    info_path: String,
+    deletion_date: String,
+    size: Option<u64>,
+    file_type: Option<&'static str>,
+    trash_dir: Option<String>,
+    encoded: bool,
+}
+
+impl Record {
+    fn new(index: usize, info: &TrashInfo) -> Self {
+        let trash_dir = info.trash_dir();
+        let encoded = [&*info.path, &*info.deleted_path, &*info.info_path]
+            .iter()
+            .copied()
+            .chain(trash_dir.as_ref().map(TrashDir::path))
+            .any(|path| path.to_str().is_none());
+        let string = |path: &Path| match path.to_str() {
+            Some(path) if !encoded => path.to_owned(),
+            _ => encode_path(path),
+        };
+
+        Record {
+            index,
+            path: string(&info.path),
+            deleted_path: string(&info.deleted_path),
+            info_path: string(&info.info_path),
+            deletion_date: info.deletion_date.to_rfc3339(),
+            size: info.size().ok(),
+            file_type: info.file_kind().ok().map(|kind| kind.as_str()),
+            trash_dir: trash_dir.as_ref().map(|trash_dir| string(trash_dir.path())),
+            encoded,
+        }
+    }
 }
 
 /// List the contents of a trash directory
 pub fn list(options: ListOptions) -> Result<()> {
-    let trash_dir = TrashDir::from_opt(options.trash_dir);
+    let trash_dirs = options.trash_dir.trash_dirs();
+    let filters = options.filter.into_filters()?;
 
-    let mut files = trash_dir
+    let mut files = read_entries(&trash_dirs);
+    // the indices are the positions in the full, unsorted list
+    let indices = files
         .iter()
-        .unwrap()
-        .filter_map(|entry| match entry {
-            Ok(info) => Some(info),
-            Err(err) => {
-                eprintln!("failed to get file info: {:?}", err);
-                None
+        .enumerate()
+        .map(|(i, info)| (info.info_path.clone(), i))
+        .collect::<HashMap<_, _>>();
+    let index = |info: &TrashInfo| indices[&info.info_path];
+
+    files.retain(|info| Filter::matches_all(&filters, info));
+    options.sort.sort(&mut files);
+    if options.reverse {
+        files.reverse();
+    }
+    if let Some(limit) = options.limit {
+        files.truncate(limit);
+    }
+
+    let terminator = if options.null { b'\0' } else { b'\n' };
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+
+    match options.format {
+        Format::Plain => {
+            for info in files.iter() {
+                if options.show_index {
+                    write!(out, "{}\t", index(info))?;
+                }
+                write!(out, "{}\t", info.deletion_date)?;
+                out.write_all(info.path.as_os_str().as_bytes())?;
+                out.write_all(&[terminator])?;
+            }
+        }
+        Format::Json => {
+            let records = files
+                .iter()
+                .map(|info| Record::new(index(info), info))
+                .collect::<Vec<_>>();
+            serde_json::to_writer_pretty(&mut out, &records)?;
+            writeln!(out)?;
+        }
+        Format::Ndjson => {
+            for info in files.iter() {
+                serde_json::to_writer(&mut out, &Record::new(index(info), info))?;
+                out.write_all(&[terminator])?;
+            }
+        }
+        Format::Csv | Format::Tsv => {
+            let delimiter = if options.format == Format::Tsv {
+                b'\t'
+            } else {
+                b','
+            };
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .terminator(csv::Terminator::Any(terminator))
+                .from_writer(&mut out);
+            for info in files.iter() {
+                writer.serialize(Record::new(index(info), info))?;
             }
-        })
-        .collect::<Vec<_>>();
-    files.sort_unstable_by_key(|info| info.deletion_date);
-    for info in files {
-        println!("{}\t{}", info.deletion_date, info.path.to_str().unwrap());
+            writer.flush()?;
+        }
     }
 
+    out.flush()?;
     Ok(())
 }
@@ -1,11 +1,78 @@
 //! Operations that garbage can do.
 
+mod check;
 mod empty;
 mod list;
 mod put;
 mod restore;
+mod select;
+mod stats;
+mod tui;
+mod undo;
 
+pub use self::check::{check, CheckOptions};
 pub use self::empty::{empty, EmptyOptions};
 pub use self::list::{list, ListOptions};
 pub use self::put::{put, PutOptions};
 pub use self::restore::{restore, RestoreOptions};
+pub use self::select::{
+    DateFilterOptions, FilterOptions, PathFilterOptions, ThreadOptions, TrashDirOptions,
+};
+pub use self::stats::{stats, StatsOptions};
+pub use self::tui::{tui, TuiOptions};
+pub use self::undo::{undo, UndoOptions};
+
+use std::ffi::OsStr;
+use std::os::unix::fs::MetadataExt;
+
+use crate::{TrashDir, TrashInfo};
+
+/// Read every entry of the given trash directories, sorted by deletion date.
+///
+/// The order is the same every time, since indices into it are shown to the user.
+///
+/// Entries (or whole trash directories) that can't be read are reported and skipped.
+fn read_entries(trash_dirs: &[TrashDir]) -> Vec<TrashInfo> {
+    let (files, errors) = read_entries_quietly(trash_dirs);
+    for err in errors.iter() {
+        eprintln!("{}", err);
+    }
+    files
+}
+
+/// Like `read_entries`, but return the errors instead of printing them.
+fn read_entries_quietly(trash_dirs: &[TrashDir]) -> (Vec<TrashInfo>, Vec<String>) {
+    let mut errors = Vec::new();
+    let mut files = Vec::new();
+    for trash_dir in trash_dirs.iter() {
+        let iter = match trash_dir.iter() {
+            Ok(iter) => iter,
+            Err(err) => {
+                errors.push(format!(
+                    "failed to read trash directory {}: {:?}",
+                    trash_dir.path().display(),
+                    err
+                ));
+                continue;
+            }
+        };
+        for entry in iter {
+            match entry {
+                Ok(info) => files.push(info),
+                Err(err) => errors.push(format!("failed to get file info: {:?}", err)),
+            }
+        }
+    }
+    // deletion dates only go down to the second, so entries trashed in the same second
+    // are ordered by when their .trashinfo file was written, and then by name
+    files.sort_by_cached_key(|info| {
+        let written = info
+            .info_path
+            .metadata()
+            .map(|meta| (meta.mtime(), meta.mtime_nsec()))
+            .unwrap_or_default();
+        let name = info.deleted_path.file_name().map(OsStr::to_os_string);
+        (info.deletion_date, written, name)
+    });
+    (files, errors)
+}
@@ -1,32 +1,66 @@
 use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fs::{self, File};
 use std::io::{self, Write};
-use std::os::unix::fs::PermissionsExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::str;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::Local;
 
+use crate::copy;
+use crate::journal::{self, Batch};
+use crate::mounts::should_use_topdir_trash;
+use crate::ops::ThreadOptions;
+use crate::protect::{Protection, Reason};
 use crate::utils;
-use crate::{TrashDir, TrashInfo};
+use crate::{DirectorySizes, TrashDir, TrashInfo};
 use crate::{HOME_MOUNT, MOUNTS};
 
+/// Where copies are put together before they're moved into `$trash/files`
+const STAGING_DIR: &str = "staging";
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Refusing to remove directory {0} without '-r' option")]
     MissingRecursiveOption(PathBuf),
 
+    #[error("Refusing to remove non-empty directory {0} without '-r' option")]
+    DirectoryNotEmpty(PathBuf),
+
     #[error("Refusing to remove '.' or '..', skipping...")]
     CannotTrashDotDirs,
 
-    #[error("Cancelled by user.")]
-    CancelledByUser,
+    #[error("Refusing to remove '/' recursively, pass --no-preserve-root to override")]
+    PreserveRoot,
+
+    #[error("Refusing to remove {0}, since {1} inside of it is on another filesystem")]
+    OtherFileSystem(PathBuf, PathBuf),
+
+    #[error("Refusing to remove {0}, since {1}")]
+    Protected(PathBuf, Reason),
+
+    #[error("Cannot remove {0}: no such file or directory")]
+    NotFound(PathBuf),
+
+    #[error("Missing operand, pass the paths to be trashed")]
+    MissingOperand,
+
+    #[error("Failed to trash {0} file(s)")]
+    Failed(usize),
 }
 
 /// Options to pass to put
+///
+/// These follow GNU rm, so that garbage put can stand in for it.
 #[derive(StructOpt)]
 pub struct PutOptions {
-    /// The target path to be trashed
+    /// The target path to be trashed.
+    ///
+    /// Paths that start with '-' can be given after '--'.
     #[structopt(parse(from_os_str))]
     paths: Vec<PathBuf>,
 
@@ -35,17 +69,43 @@ pub struct PutOptions {
     dry: bool,
 
     /// Prompt before every removal
-    #[structopt(long = "prompt", short = "i")]
+    #[structopt(long = "prompt", short = "i", overrides_with_all = &["force", "prompt-once"])]
     prompt: bool,
 
-    /// Trashes directories recursively (ignored)
+    /// Prompt once before removing more than three files, or when removing
+    /// recursively
+    #[structopt(short = "I", overrides_with_all = &["force", "prompt"])]
+    prompt_once: bool,
+
+    /// Trash directories and their contents
     #[structopt(long = "recursive", short = "r")]
-    _recursive: bool,
+    recursive: bool,
+
+    // the same as -r, like in rm
+    #[structopt(short = "R", hidden = true)]
+    recursive_alias: bool,
+
+    /// Trash empty directories
+    #[structopt(long = "dir", short = "d")]
+    dir: bool,
 
-    /// Suppress prompts/messages
-    #[structopt(long = "force", short = "f")]
+    /// Ignore nonexistent files, and never prompt
+    #[structopt(long = "force", short = "f", overrides_with_all = &["prompt", "prompt-once"])]
     force: bool,
 
+    /// Refuse to trash a directory that has another filesystem mounted inside of it
+    #[structopt(long = "one-file-system")]
+    one_file_system: bool,
+
+    /// Refuse to trash '/' (the default)
+    #[structopt(long = "preserve-root", overrides_with = "no-preserve-root")]
+    _preserve_root: bool,
+
+    /// Don't treat '/' specially, like in rm (though it's still protected, along
+    /// with the other paths that are never trashed)
+    #[structopt(long = "no-preserve-root", overrides_with = "preserve-root")]
+    no_preserve_root: bool,
+
     /// Put all the trashed files into this trash directory
     /// regardless of what filesystem is on.
     ///
@@ -56,36 +116,187 @@ pub struct PutOptions {
     /// automatically for each file.
     #[structopt(long = "trash-dir", parse(from_os_str))]
     trash_dir: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    threads: ThreadOptions,
+}
+
+impl PutOptions {
+    fn recursive(&self) -> bool {
+        self.recursive || self.recursive_alias
+    }
 }
 
 /// Throw some files into the trash.
 pub fn put(options: PutOptions) -> Result<()> {
+    options.threads.apply();
+    if options.paths.is_empty() {
+        ensure!(options.force, Error::MissingOperand);
+        return Ok(());
+    }
+
+    if options.prompt_once && (options.paths.len() > 3 || options.recursive()) {
+        let question = format!(
+            "Remove {} argument(s){}?",
+            options.paths.len(),
+            if options.recursive() {
+                " recursively"
+            } else {
+                ""
+            }
+        );
+        if !confirm(&question)? {
+            return Ok(());
+        }
+    }
+
+    let trash_dir = options.trash_dir.as_ref().map(TrashDir::from);
+    let protection = Protection::load(trash_dir.as_ref())?;
+
+    let mut batch = Batch::new();
+    let mut failed = 0;
     for path in options.paths.iter() {
-        // don't allow deleting '.' or '..'
-        let current_dir = env::current_dir()?;
+        match put_path(path, &options, &protection) {
+            Ok(Some(info)) => batch.push(&info),
+            Ok(None) => (),
+            Err(err) => {
+                eprintln!("{:#}", err);
+                failed += 1;
+            }
+        }
+    }
+
+    // everything is already in the trash, so only undo is lost if this fails
+    if !batch.entries.is_empty() {
+        if let Err(err) = journal::append(&batch) {
+            eprintln!("failed to record this put in the journal: {}", err);
+        }
+    }
+
+    ensure!(failed == 0, Error::Failed(failed));
+    Ok(())
+}
+
+/// Trash a single path from the command line, returning the entry that was created
+/// for it in the trash (if any).
+fn put_path(
+    path: &Path,
+    options: &PutOptions,
+    protection: &Protection,
+) -> Result<Option<TrashInfo>> {
+    // don't allow deleting '.' or '..'
+    let current_dir = env::current_dir()?;
+    let absolute = utils::into_absolute(path)?;
+    ensure!(
+        !(is_dot_or_dot_dot(path)
+            || absolute == current_dir
+            || Some(absolute.as_path()) == current_dir.parent()),
+        Error::CannotTrashDotDirs
+    );
+
+    // symlinks are trashed themselves, so they don't have to point anywhere
+    let meta = match path.symlink_metadata() {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            ensure!(options.force, Error::NotFound(path.to_path_buf()));
+            return Ok(None);
+        }
+        Err(err) => {
+            return Err(anyhow::Error::from(err))
+                .with_context(|| format!("Cannot remove {}", path.display()))
+        }
+    };
+
+    if meta.is_dir() {
+        if !options.recursive() {
+            ensure!(
+                options.dir,
+                Error::MissingRecursiveOption(path.to_path_buf())
+            );
+            ensure!(
+                fs::read_dir(path)?.next().is_none(),
+                Error::DirectoryNotEmpty(path.to_path_buf())
+            );
+        }
+
+        let root = fs::metadata("/")?;
         ensure!(
-            !(utils::into_absolute(&path)? == current_dir.as_path()
-                || (current_dir.parent().is_some()
-                    && utils::into_absolute(&path)? == current_dir.parent().unwrap())),
-            Error::CannotTrashDotDirs
+            options.no_preserve_root || (meta.dev(), meta.ino()) != (root.dev(), root.ino()),
+            Error::PreserveRoot
         );
+    }
 
-        // pick the best strategy for deleting this particular file
-        let strategy = if let Some(ref trash_dir) = options.trash_dir {
-            DeletionStrategy::Fixed(TrashDir::from(trash_dir))
-        } else {
-            DeletionStrategy::pick_strategy(path)?
-        };
-        // println!("Strategy: {:?}", strategy);
+    if let Err(reason) = protection.check(path) {
+        bail!(Error::Protected(path.to_path_buf(), reason));
+    }
 
-        if options.dry {
-            eprintln!("Dry-deleting: {}", path.to_str().unwrap());
-        } else if let Err(err) = strategy.delete(path, &options) {
-            eprintln!("{}", err);
+    // pick the best strategy for deleting this particular file
+    let strategy = if let Some(ref trash_dir) = options.trash_dir {
+        DeletionStrategy::Fixed(TrashDir::from(trash_dir))
+    } else {
+        DeletionStrategy::pick_strategy(path)?
+    };
+
+    if meta.is_dir() {
+        // a copy would leave out whatever is mounted inside, so copies can't cross
+        // filesystems either
+        if options.one_file_system || strategy.get_target_trash().1 {
+            if let Some(mount) = MOUNTS.find_mount_below(path) {
+                bail!(Error::OtherFileSystem(path.to_path_buf(), mount));
+            }
         }
     }
 
-    Ok(())
+    if options.dry {
+        eprintln!("Dry-deleting: {}", path.display());
+        return Ok(None);
+    }
+    strategy.delete(path, options.force, options.prompt)
+}
+
+/// Move whatever is at path into the trash without asking anything, so that it can
+/// be brought back later.
+pub(super) fn trash_path(path: &Path) -> Result<TrashInfo> {
+    let strategy = DeletionStrategy::pick_strategy(path)?;
+    let info = strategy.delete(path, true, false)?;
+    Ok(info.expect("nothing is asked when forced"))
+}
+
+/// Check whether the last component of path is '.' or '..', which rm refuses
+/// to remove even if it's something like 'dir/..'.
+fn is_dot_or_dot_dot(path: &Path) -> bool {
+    let bytes = path.as_os_str().as_bytes();
+    let name = match bytes.iter().rposition(|b| *b != b'/') {
+        Some(end) => {
+            let bytes = &bytes[..=end];
+            let start = bytes.iter().rposition(|b| *b == b'/').map_or(0, |i| i + 1);
+            &bytes[start..]
+        }
+        None => return false,
+    };
+    name == b"." || name == b".."
+}
+
+/// Ask a yes or no question on stderr. Running out of input counts as no.
+fn confirm(question: &str) -> Result<bool> {
+    eprint!("{} [Y/n] ", question);
+    io::stderr().flush()?;
+
+    loop {
+        let stdin = io::stdin();
+        let mut s = String::new();
+        if stdin.read_line(&mut s)? == 0 {
+            return Ok(false);
+        }
+        match s.trim().to_lowercase().as_str() {
+            "yes" | "y" => return Ok(true),
+            "no" | "n" => return Ok(false),
+            _ => {
+                eprint!("Invalid response. Please type yes or no: ");
+                io::stderr().flush()?;
+            }
+        }
+    }
 }
 
 /// DeletionStrategy describes a strategy by which a file is deleted
@@ -148,114 +359,167 @@ impl DeletionStrategy {
         }
     }
 
-    /// The actual deletion happens here
-    pub fn delete(&self, target: impl AsRef<Path>, options: &PutOptions) -> Result<()> {
+    /// The actual deletion happens here, returning the entry that was created in the
+    /// trash, or None if the user changed their mind
+    pub fn delete(
+        &self,
+        target: impl AsRef<Path>,
+        force: bool,
+        prompt: bool,
+    ) -> Result<Option<TrashInfo>> {
         let target = target.as_ref();
 
-        // this will be None if target isn't a symlink
-        let link_info = target.read_link().ok();
-
-        // file is a directory
-        // if !link_info.is_some() && target.is_dir() && !options.recursive {
-        //     bail!(Error::MissingRecursiveOption(target.to_path_buf()));
-        // }
-
         let (trash_dir, requires_copy) = self.get_target_trash();
 
-        // prompt if not suppressed
-        // TODO: streamline this logic better
-        if !options.force && (requires_copy || options.prompt) {
-            // TODO: actually handle prompting instead of manually flushing
-            if requires_copy {
-                eprint!(
-                    "Removing file '{}' requires potentially expensive copying. Continue? [Y/n] ",
-                    target.to_str().unwrap()
-                );
-            } else if options.prompt {
-                eprint!("Remove file '{}'? [Y/n] ", target.to_str().unwrap());
-            }
-            io::stderr().flush()?;
-
-            let should_continue = loop {
-                let stdin = io::stdin();
-                let mut s = String::new();
-                stdin.read_line(&mut s).unwrap();
-                match s.trim().to_lowercase().as_str() {
-                    "yes" | "y" => break true,
-                    "no" | "n" => break false,
-                    _ => {
-                        eprint!("Invalid response. Please type yes or no: ");
-                    }
-                }
+        // prompt if not suppressed, though scripts without a terminal aren't asked
+        // about copying, like rm doesn't ask them about write-protected files
+        let ask_to_copy = requires_copy && utils::stdin_is_terminal();
+        if !force && (ask_to_copy || prompt) {
+            let question = if ask_to_copy {
+                format!(
+                    "Removing file '{}' requires potentially expensive copying. Continue?",
+                    target.display()
+                )
+            } else {
+                format!("Remove file '{}'?", target.display())
             };
-            if !should_continue {
-                bail!(Error::CancelledByUser);
+            if !confirm(&question)? {
+                return Ok(None);
             }
         }
 
-        // preparing metadata
-        let now = Local::now();
-        let elapsed = now.timestamp_millis();
-        let file_name = format!(
-            "{}.{}",
-            elapsed,
-            target.file_name().unwrap().to_str().unwrap()
-        );
+        // reserve a name in the trash, based on the original file name
+        let original_name = target
+            .file_name()
+            .ok_or_else(|| anyhow!("couldn't get file name of {}", target.display()))?;
+        let (file_name, trash_info_file) = trash_dir.reserve_name(original_name)?;
 
+        let mut info_name = file_name.clone();
+        info_name.push(".trashinfo");
         let trash_file_path = trash_dir.files_dir()?.join(&file_name);
-        let trash_info_path = trash_dir.info_dir()?.join(file_name + ".trashinfo");
+        let trash_info_path = trash_dir.info_dir()?.join(info_name);
 
+        // preparing metadata
         let trash_info = TrashInfo {
             path: utils::into_absolute(target)?,
-            deletion_date: now,
+            deletion_date: Local::now(),
             deleted_path: trash_file_path.clone(),
             info_path: trash_info_path.clone(),
+            topdir: trash_dir.topdir(),
+            cached_size: None,
         };
-        {
-            let trash_info_file = File::create(trash_info_path)?;
-            trash_info.write(&trash_info_file)?;
+
+        // move the file over
+        let result = trash_info
+            .write(&trash_info_file)
+            .and_then(|_| trash_info_file.sync_all())
+            .map_err(anyhow::Error::from)
+            .and_then(|_| {
+                if requires_copy {
+                    if let Err(err) = clean_staging(trash_dir) {
+                        eprintln!("failed to clean up the staging directory: {}", err);
+                    }
+                    stage_copy(trash_dir, target, &file_name, &trash_file_path)
+                } else {
+                    Ok(fs::rename(target, &trash_file_path)?)
+                }
+            });
+
+        // give the reserved name back if anything went wrong
+        if let Err(err) = result {
+            fs::remove_file(&trash_info_path)?;
+            return Err(err);
+        }
+
+        // the cache is only a hint for other tools, so don't fail the whole put over it
+        if trash_file_path.is_dir() {
+            if let Err(err) = DirectorySizes::update(trash_dir, |sizes| sizes.insert(&trash_info)) {
+                eprintln!("failed to update directorysizes: {}", err);
+            }
         }
 
-        // copy the file over
+        // there's a complete copy in the trash now, so it stays there even if this fails
         if requires_copy {
-            utils::recursive_copy(&target, &trash_file_path)?;
-            fs::remove_dir_all(&target)?;
-        } else {
-            fs::rename(&target, &trash_file_path)?;
+            utils::remove_all(target).with_context(|| {
+                format!(
+                    "{} was copied to the trash, but couldn't be removed",
+                    target.display()
+                )
+            })?;
         }
 
-        Ok(())
+        Ok(Some(trash_info))
     }
 }
 
-/// Can we use $topdir/.Trash?
+/// Copy target into the trash as `file_name`, without ever leaving a partial copy in
+/// `$trash/files`.
 ///
-/// 1. If it doesn't exist, don't create it.
-/// 2. All users should be able to write to it
-/// 3. It must have sticky-bit permissions if the filesystem supports it.
-/// 4. The directory must not be a symbolic link.
-fn should_use_topdir_trash(mount: impl AsRef<Path>) -> bool {
-    let mount = mount.as_ref();
-    let trash_dir = mount.join(".Trash");
-
-    if !trash_dir.exists() {
-        return false;
+/// The copy is made in `$trash/staging` and synced to disk before it's renamed into
+/// place, so after a crash there's either a complete copy in the trash or none at all.
+fn stage_copy(
+    trash_dir: &TrashDir,
+    target: &Path,
+    file_name: &OsStr,
+    trash_file_path: &Path,
+) -> Result<()> {
+    let staging_dir = trash_dir.path().join(STAGING_DIR);
+    fs::create_dir_all(&staging_dir)?;
+
+    // staged copies are named after the process making them, see clean_staging
+    let mut staged_name = OsString::from(format!("{}.", process::id()));
+    staged_name.push(file_name);
+    let staged_path = staging_dir.join(staged_name);
+
+    copy::copy_into_place(target, &staged_path, || {
+        utils::sync_filesystem(&staging_dir)?;
+        Ok(fs::rename(&staged_path, trash_file_path)?)
+    })?;
+
+    // the copy is already in place, so this only makes the rename itself durable
+    if let Some(files_dir) = trash_file_path.parent() {
+        if let Ok(dir) = File::open(files_dir) {
+            let _ = dir.sync_all();
+        }
     }
+    Ok(())
+}
 
-    let dir = match File::open(trash_dir) {
-        Ok(file) => file,
-        Err(_) => return false,
-    };
-    let meta = match dir.metadata() {
-        Ok(meta) => meta,
-        Err(_) => return false,
-    };
-    if meta.file_type().is_symlink() {
-        return false;
+/// Remove the copies that interrupted puts left in `$trash/staging`.
+///
+/// Only copies made by processes that aren't running anymore are removed, along with
+/// the .trashinfo files that were reserved for them.
+fn clean_staging(trash_dir: &TrashDir) -> Result<()> {
+    let staging_dir = trash_dir.path().join(STAGING_DIR);
+    if !staging_dir.is_dir() {
+        return Ok(());
     }
-    let perms = meta.permissions();
 
-    perms.mode() & 0o1000 > 0
+    for entry in fs::read_dir(&staging_dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().as_bytes();
+        let (pid, file_name) = match name.iter().position(|b| *b == b'.') {
+            Some(split) => (&name[..split], OsStr::from_bytes(&name[split + 1..])),
+            None => continue,
+        };
+        match str::from_utf8(pid).ok().and_then(|pid| pid.parse().ok()) {
+            Some(pid) if !utils::process_exists(pid) => (),
+            _ => continue,
+        }
+
+        utils::force_remove_all(&path)?;
+
+        // the copy never made it into $trash/files, so its name is free again
+        let mut info_name = file_name.to_os_string();
+        info_name.push(".trashinfo");
+        let info_path = trash_dir.path().join("info").join(info_name);
+        let deleted_path = trash_dir.path().join("files").join(file_name);
+        if deleted_path.symlink_metadata().is_err() && info_path.exists() {
+            fs::remove_file(info_path)?;
+        }
+    }
+
+    Ok(())
 }
 
 /// Can we use $topdir/.Trash-uid?
@@ -267,5 +531,5 @@ fn should_use_topdir_trash_uid(path: impl AsRef<Path>) -> bool {
             Err(_) => return false,
         };
     }
-    return true;
+    true
 }
@@ -1,72 +1,455 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsString;
 use std::fs;
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::thread;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 
-use crate::TrashDir;
+use crate::fuzzy;
+use crate::ops::put::trash_path;
+use crate::ops::select::{parse_selection, pattern_filter};
+use crate::ops::{read_entries, ThreadOptions, TrashDirOptions};
+use crate::utils;
+use crate::{DirectorySizes, TrashInfo};
+
+/// How many of the best fuzzy matches are offered
+const FUZZY_CHOICES: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No files in the trash match {0}")]
+    NoMatches(String),
+
+    #[error("Invalid index {0}, there are only {1} files in the trash")]
+    InvalidIndex(usize, usize),
+
+    #[error("Failed to restore {0} file(s)")]
+    Failed(usize),
+
+    #[error("Refusing to overwrite existing file {0}")]
+    AlreadyExists(PathBuf),
+
+    #[error("Directory {0} doesn't exist, pass --parents to create it")]
+    MissingParent(PathBuf),
+
+    #[error("Unknown conflict policy '{0}', expected one of: abort, overwrite, rename, prompt")]
+    UnknownConflictPolicy(String),
+}
+
+/// What to do when a file already exists at the path being restored to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConflictPolicy {
+    /// Leave the existing file alone and don't restore
+    Abort,
+
+    /// Move the existing file into the trash, and restore in its place
+    Overwrite,
+
+    /// Restore next to the existing file, with a numeric suffix added to the name
+    Rename,
+
+    /// Ask what to do
+    Prompt,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "abort" => Ok(ConflictPolicy::Abort),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "rename" => Ok(ConflictPolicy::Rename),
+            "prompt" => Ok(ConflictPolicy::Prompt),
+            _ => Err(Error::UnknownConflictPolicy(s.to_owned())),
+        }
+    }
+}
 
 /// Options to pass to restore
 #[derive(StructOpt)]
 pub struct RestoreOptions {
-    /// The path to the trash directory to restore from.
-    /// By default, this is your home directory's trash ($XDG_DATA_HOME/Trash)
-    #[structopt(long = "trash-dir", parse(from_os_str))]
-    trash_dir: Option<PathBuf>,
+    /// Original paths (or glob patterns matching original paths) of the files to restore.
+    ///
+    /// Patterns without a '/' are matched against the file name only, so
+    /// `garbage restore '*.o'` restores every trashed object file. Other relative
+    /// paths are relative to the current directory. If the same path was
+    /// trashed several times, only the most recently deleted one is restored,
+    /// unless --all-matching is passed.
+    /// If neither this nor --index is given, you'll be prompted to pick a file.
+    patterns: Vec<String>,
+
+    /// Restore every file that matches, instead of only the most recently deleted
+    /// one for each path
+    #[structopt(long = "all-matching", short = "a")]
+    all_matching: bool,
+
+    /// Restore the file with this index, as shown by list --show-index (can be given
+    /// multiple times)
+    #[structopt(long = "index", conflicts_with = "patterns")]
+    indices: Vec<usize>,
+
+    /// Pick the file to restore from the best fuzzy matches of this query
+    /// against the original paths. A few typos are tolerated
+    #[structopt(long = "fuzzy", short = "z", conflicts_with_all = &["patterns", "indices"])]
+    fuzzy: Option<String>,
+
+    /// Let this command (like 'fzf -m') pick the files to restore.
+    /// It's run by the shell and gets one line per file on stdin: the index, the
+    /// deletion date and the original path, separated by tabs. The lines it prints
+    /// are the ones that get restored
+    #[structopt(long = "picker", conflicts_with_all = &["patterns", "indices", "fuzzy"])]
+    picker: Option<String>,
+
+    /// What to do if something already exists at the original path:
+    /// abort, overwrite (the existing file is trashed), rename (add a numeric suffix)
+    /// or prompt
+    #[structopt(long = "conflict", default_value = "abort")]
+    conflict: ConflictPolicy,
+
+    /// Restore the files into this directory, instead of where they were deleted from
+    #[structopt(long = "to", parse(from_os_str))]
+    to: Option<PathBuf>,
+
+    /// Create any missing parent directories of the path being restored to
+    #[structopt(long = "parents", short = "p")]
+    parents: bool,
+
+    #[structopt(flatten)]
+    trash_dir: TrashDirOptions,
+
+    #[structopt(flatten)]
+    threads: ThreadOptions,
 }
 
 /// Restore files from a trash directory
 pub fn restore(options: RestoreOptions) -> Result<()> {
-    let trash_dir = TrashDir::from_opt(options.trash_dir);
+    options.threads.apply();
+    let mut trash_dirs = options.trash_dir.trash_dirs();
+    trash_dirs.retain(|trash_dir| matches!(trash_dir.check_info_dir(), Ok(Some(_))));
 
-    if trash_dir.check_info_dir()?.is_none() {
+    if trash_dirs.is_empty() {
         bail!("There's no trash directory here.");
     }
 
     // get list of files sorted by deletion date
     // TODO: possible to get this to be streaming?
-    let files = {
-        let mut files = trash_dir
-            .iter()
-            .unwrap()
-            .filter_map(|entry| match entry {
-                Ok(info) => Some(info),
-                Err(err) => {
-                    eprintln!("failed to get file info: {:?}", err);
-                    None
+    let files = read_entries(&trash_dirs);
+
+    if files.is_empty() {
+        bail!("No files in the trash.");
+    }
+
+    // figure out which files to restore
+    let mut selected = BTreeSet::new();
+    let mut unmatched = Vec::new();
+    if !options.indices.is_empty() {
+        for &i in options.indices.iter() {
+            ensure!(i < files.len(), Error::InvalidIndex(i, files.len()));
+            selected.insert(i);
+        }
+    } else if let Some(ref query) = options.fuzzy {
+        selected.extend(prompt_fuzzy(&files, query)?);
+    } else if let Some(ref picker) = options.picker {
+        selected.extend(run_picker(&files, picker)?);
+    } else if !options.patterns.is_empty() {
+        for pattern in options.patterns.iter() {
+            let matches = match_pattern(&files, pattern)?;
+            if matches.is_empty() {
+                unmatched.push(pattern.as_str());
+            } else if options.all_matching {
+                selected.extend(matches);
+            } else {
+                // files are sorted by deletion date, so the newest one for each path wins
+                let mut newest = HashMap::new();
+                for i in matches {
+                    newest.insert(&files[i].path, i);
                 }
-            })
-            .collect::<Vec<_>>();
-        files.sort_unstable_by_key(|info| info.deletion_date);
-        files
-    };
+                selected.extend(newest.values());
+            }
+        }
+    } else {
+        match prompt_index(&files)? {
+            Some(i) => {
+                selected.insert(i);
+            }
+            None => {
+                eprintln!("Invalid number.");
+                return Ok(());
+            }
+        }
+    }
+
+    let mut failed = 0;
+    for i in selected {
+        let info = &files[i];
+        match restore_entry(
+            info,
+            options.to.as_deref(),
+            options.conflict,
+            options.parents,
+        ) {
+            Ok(target) => eprintln!("restored {:?} to {:?}", info.path, target),
+            Err(err) => {
+                eprintln!("failed to restore {}: {}", info.path.display(), err);
+                failed += 1;
+            }
+        }
+    }
+
+    ensure!(unmatched.is_empty(), Error::NoMatches(unmatched.join(", ")));
+    ensure!(failed == 0, Error::Failed(failed));
+    Ok(())
+}
+
+/// Find the indices of every file whose original path matches the given pattern.
+fn match_pattern(files: &[TrashInfo], pattern: &str) -> Result<Vec<usize>> {
+    let filter = pattern_filter(pattern)?;
+    Ok(files
+        .iter()
+        .enumerate()
+        .filter(|(_, info)| filter.matches(info))
+        .map(|(i, _)| i)
+        .collect())
+}
+
+/// Show the files that match a fuzzy query best, and ask which ones to restore.
+fn prompt_fuzzy(files: &[TrashInfo], query: &str) -> Result<Vec<usize>> {
+    let paths = files
+        .iter()
+        .map(|info| info.path.to_string_lossy())
+        .collect::<Vec<_>>();
+    let mut ranked = fuzzy::rank(query, paths.iter().map(|path| path.as_ref()));
+    ensure!(!ranked.is_empty(), Error::NoMatches(query.to_owned()));
+    ranked.truncate(FUZZY_CHOICES);
+
+    for (i, &index) in ranked.iter().enumerate() {
+        let info = &files[index];
+        println!("[{}]\t{}\t{}", i, info.deletion_date, info.path.display());
+    }
+    eprint!(
+        "which files to restore? (like '0 2 4-6', or 'all') [0..{}, default 0] ",
+        ranked.len() - 1
+    );
+    io::stderr().flush()?;
 
-    if files.len() == 0 {
-        bail!("No files in this trash directory.");
+    // running out of input picks nothing, only an empty line picks the default
+    let stdin = io::stdin();
+    let mut s = String::new();
+    if stdin.read_line(&mut s)? == 0 {
+        eprintln!();
+        eprintln!("Not restoring anything.");
+        return Ok(Vec::new());
     }
+    if s.trim().is_empty() {
+        return Ok(vec![ranked[0]]);
+    }
+
+    let picked = parse_selection(&s, ranked.len())?;
+    Ok(picked.into_iter().map(|i| ranked[i]).collect())
+}
 
+/// Let an external command pick files, and find out which ones it picked.
+fn run_picker(files: &[TrashInfo], picker: &str) -> Result<Vec<usize>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(picker)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to run picker '{}'", picker))?;
+
+    // write from another thread, since the picker may not read everything
+    // before it starts printing
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let lines = files
+        .iter()
+        .enumerate()
+        .map(|(i, info)| {
+            // the index is what counts, so the path only has to stay on one line
+            let path = info.path.to_string_lossy().replace('\n', "\\n");
+            format!("{}\t{}\t{}\n", i, info.deletion_date, path)
+        })
+        .collect::<String>();
+    let writer = thread::spawn(move || {
+        // the picker is allowed to quit early
+        let _ = stdin.write_all(lines.as_bytes());
+    });
+
+    let output = child.wait_with_output()?;
+    let _ = writer.join();
+
+    let mut picked = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let index = line.split('\t').next().unwrap_or("").trim();
+        match index.parse::<usize>() {
+            Ok(i) if i < files.len() => picked.push(i),
+            _ => bail!(
+                "picker printed '{}', which isn't one of the lines it got",
+                line
+            ),
+        }
+    }
+    // pickers like fzf exit with an error when nothing was picked
+    if picked.is_empty() && !output.status.success() {
+        bail!("picker '{}' failed ({})", picker, output.status);
+    }
+    Ok(picked)
+}
+
+/// Print the numbered list of files and ask which one to restore.
+fn prompt_index(files: &[TrashInfo]) -> Result<Option<usize>> {
     for (i, info) in files.iter().enumerate() {
-        println!(
-            "[{}]\t{}\t{}",
-            i,
-            info.deletion_date,
-            info.path.to_str().unwrap()
-        );
+        println!("[{}]\t{}\t{}", i, info.deletion_date, info.path.display());
     }
 
     let stdin = io::stdin();
     let mut s = String::new();
     eprintln!("which file to restore? [0..{}]", files.len() - 1);
-    stdin.read_line(&mut s).unwrap();
+    stdin.read_line(&mut s)?;
+
+    Ok(match s.trim_end().parse::<usize>() {
+        Ok(i) if i < files.len() => Some(i),
+        _ => None,
+    })
+}
 
-    match s.trim_end().parse::<usize>() {
-        Ok(i) if i < files.len() => {
-            let info = &files[i]; // should never fail since we just checked
-            eprintln!("moving {:?} to {:?}", &info.deleted_path, &info.path);
-            fs::remove_file(&info.info_path)?;
-            fs::rename(&info.deleted_path, &info.path)?;
+/// Move a single file out of the trash and back to its original location, or into
+/// the directory `to`. Returns where the file ended up.
+///
+/// The .trashinfo file is only removed once the file is back in place, so a failed
+/// restore leaves the entry in the trash.
+pub(super) fn restore_entry(
+    info: &TrashInfo,
+    to: Option<&Path>,
+    conflict: ConflictPolicy,
+    parents: bool,
+) -> Result<PathBuf> {
+    let mut target = match to {
+        Some(dir) => {
+            let name = info
+                .path
+                .file_name()
+                .ok_or_else(|| anyhow!("couldn't get file name of {}", info.path.display()))?;
+            dir.join(name)
+        }
+        None => info.path.clone(),
+    };
+
+    if let Some(parent) = target.parent() {
+        if !parent.exists() {
+            ensure!(parents, Error::MissingParent(parent.to_path_buf()));
+            fs::create_dir_all(parent)?;
         }
-        _ => eprintln!("Invalid number."),
     }
-    Ok(())
+
+    let mut policy = conflict;
+    if target.symlink_metadata().is_ok() {
+        if policy == ConflictPolicy::Prompt {
+            policy = prompt_conflict(&target)?;
+        }
+        match policy {
+            ConflictPolicy::Overwrite => (),
+            ConflictPolicy::Rename => target = free_path(&target)?,
+            _ => bail!(Error::AlreadyExists(target)),
+        }
+    }
+
+    // what's being overwritten goes into the trash itself, so that it can be undone
+    let displaced = if policy == ConflictPolicy::Overwrite && target.symlink_metadata().is_ok() {
+        Some(
+            trash_path(&target)
+                .with_context(|| format!("couldn't move {} out of the way", target.display()))?,
+        )
+    } else {
+        None
+    };
+
+    // something could still show up at the target in the meantime, and it's left alone
+    let result = loop {
+        let err = match utils::move_path(&info.deleted_path, &target) {
+            Ok(()) => break Ok(()),
+            Err(err) => err,
+        };
+        let exists = err
+            .downcast_ref::<io::Error>()
+            .is_some_and(|err| err.kind() == io::ErrorKind::AlreadyExists);
+        match policy {
+            _ if !exists => break Err(err),
+            ConflictPolicy::Rename => target = free_path(&target)?,
+            _ => break Err(Error::AlreadyExists(target.clone()).into()),
+        }
+    };
+    if let Err(err) = result {
+        if let Some(displaced) = displaced {
+            if let Err(err) = restore_entry(&displaced, None, ConflictPolicy::Abort, false) {
+                eprintln!(
+                    "failed to put {} back, it's still in the trash: {}",
+                    displaced.path.display(),
+                    err
+                );
+            }
+        }
+        return Err(err);
+    }
+    fs::remove_file(&info.info_path)?;
+
+    // the cache is only a hint for other tools, so the restore still succeeded
+    if let (Some(trash_dir), Some(name)) = (info.trash_dir(), info.deleted_path.file_name()) {
+        if let Err(err) = DirectorySizes::update(&trash_dir, |sizes| {
+            sizes.remove(name);
+            Ok(())
+        }) {
+            eprintln!("failed to update directorysizes: {}", err);
+        }
+    }
+    Ok(target)
+}
+
+/// Ask the user what to do about a file that's in the way.
+fn prompt_conflict(target: &Path) -> Result<ConflictPolicy> {
+    eprint!(
+        "'{}' already exists. [o]verwrite, [r]ename or [a]bort? ",
+        target.display()
+    );
+    io::stderr().flush()?;
+
+    loop {
+        let stdin = io::stdin();
+        let mut s = String::new();
+        if stdin.read_line(&mut s)? == 0 {
+            return Ok(ConflictPolicy::Abort);
+        }
+        match s.trim().to_lowercase().as_str() {
+            "overwrite" | "o" => return Ok(ConflictPolicy::Overwrite),
+            "rename" | "r" => return Ok(ConflictPolicy::Rename),
+            "abort" | "a" => return Ok(ConflictPolicy::Abort),
+            _ => {
+                eprint!("Invalid response. Please type overwrite, rename or abort: ");
+                io::stderr().flush()?;
+            }
+        }
+    }
+}
+
+/// Find a path next to `path` that doesn't exist yet, by adding a numeric suffix.
+fn free_path(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("couldn't get file name of {}", path.display()))?;
+
+    let mut counter = 1;
+    loop {
+        let mut candidate = OsString::from(name);
+        candidate.push(format!(".{}", counter));
+        let candidate = path.with_file_name(candidate);
+        if candidate.symlink_metadata().is_err() {
+            return Ok(candidate);
+        }
+        counter += 1;
+    }
 }
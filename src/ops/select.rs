@@ -0,0 +1,248 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local};
+use glob::Pattern;
+use regex::Regex;
+
+use crate::timespec::{parse_date, parse_duration};
+use crate::utils;
+use crate::walk;
+use crate::{FileKind, Filter, TrashDir};
+
+/// Options for picking out trash entries by when they were deleted
+#[derive(StructOpt)]
+pub struct DateFilterOptions {
+    /// Only files deleted before this date (like 2026-01-01 or 2026-01-01T12:00:00),
+    /// or before this long ago (like 2w or 36h)
+    #[structopt(long = "before", parse(try_from_str = parse_date))]
+    before: Option<DateTime<Local>>,
+
+    /// Only files deleted after this date (like 2026-01-01 or 2026-01-01T12:00:00),
+    /// or after this long ago (like 2w or 36h)
+    #[structopt(long = "after", parse(try_from_str = parse_date))]
+    after: Option<DateTime<Local>>,
+
+    /// Only files deleted more than this long ago (like 30d, 2w, 36h or 1d12h)
+    #[structopt(long = "older-than", parse(try_from_str = parse_duration))]
+    older_than: Option<Duration>,
+
+    /// Only files deleted less than this long ago (like 30d, 2w, 36h or 1d12h)
+    #[structopt(long = "newer-than", parse(try_from_str = parse_duration))]
+    newer_than: Option<Duration>,
+}
+
+impl DateFilterOptions {
+    /// Turn the options into the filters they describe.
+    pub fn into_filters(self) -> Vec<Filter> {
+        let now = Local::now();
+        let mut filters = Vec::new();
+        if let Some(date) = self.before {
+            filters.push(Filter::DeletedBefore(date));
+        }
+        if let Some(date) = self.after {
+            filters.push(Filter::DeletedAfter(date));
+        }
+        if let Some(duration) = self.older_than {
+            filters.push(Filter::DeletedBefore(now - duration));
+        }
+        if let Some(duration) = self.newer_than {
+            filters.push(Filter::DeletedAfter(now - duration));
+        }
+        filters
+    }
+}
+
+/// Options for picking out trash entries by their original path
+#[derive(StructOpt)]
+pub struct PathFilterOptions {
+    /// Only files whose original path matches this glob.
+    /// If the pattern doesn't contain a '/', it's matched against the file name only
+    #[structopt(long = "glob")]
+    glob: Option<Pattern>,
+
+    /// Only files whose original path matches this regular expression
+    #[structopt(long = "regex")]
+    regex: Option<Regex>,
+
+    /// Only files that were originally inside of this directory
+    #[structopt(long = "under", parse(from_os_str))]
+    under: Option<PathBuf>,
+}
+
+impl PathFilterOptions {
+    /// Turn the options into the filters they describe.
+    pub fn into_filters(self) -> Result<Vec<Filter>> {
+        let mut filters = Vec::new();
+        if let Some(pattern) = self.glob {
+            filters.push(Filter::Glob(pattern));
+        }
+        if let Some(regex) = self.regex {
+            filters.push(Filter::Regex(regex));
+        }
+        if let Some(dir) = self.under {
+            filters.push(Filter::Under(utils::into_absolute(dir)?));
+        }
+        Ok(filters)
+    }
+}
+
+/// Options for picking out which trash entries to operate on
+///
+/// All of the given conditions have to match.
+#[derive(StructOpt)]
+pub struct FilterOptions {
+    #[structopt(flatten)]
+    paths: PathFilterOptions,
+
+    #[structopt(flatten)]
+    dates: DateFilterOptions,
+
+    /// Only files of at least this size (like 512, 20K, 1.5M or 2GiB)
+    #[structopt(long = "larger-than", parse(try_from_str = utils::parse_size))]
+    larger_than: Option<u64>,
+
+    /// Only files of at most this size (like 512, 20K, 1.5M or 2GiB)
+    #[structopt(long = "smaller-than", parse(try_from_str = utils::parse_size))]
+    smaller_than: Option<u64>,
+
+    /// Only files of this type: file, directory, symlink or other
+    #[structopt(long = "type")]
+    kind: Option<FileKind>,
+}
+
+impl FilterOptions {
+    /// Turn the options into the filters they describe.
+    pub fn into_filters(self) -> Result<Vec<Filter>> {
+        let mut filters = self.paths.into_filters()?;
+        filters.extend(self.dates.into_filters());
+        if let Some(size) = self.larger_than {
+            filters.push(Filter::MinSize(size));
+        }
+        if let Some(size) = self.smaller_than {
+            filters.push(Filter::MaxSize(size));
+        }
+        if let Some(kind) = self.kind {
+            filters.push(Filter::Kind(kind));
+        }
+        Ok(filters)
+    }
+}
+
+/// Option for picking a single trash directory to operate on
+#[derive(StructOpt)]
+pub struct TrashDirOptions {
+    /// Only use this trash directory.
+    /// By default, every trash directory of the current user is used:
+    /// your home directory's trash ($XDG_DATA_HOME/Trash) and the
+    /// trash directories at the top of every mounted filesystem
+    #[structopt(long = "trash-dir", parse(from_os_str))]
+    trash_dir: Option<PathBuf>,
+}
+
+impl TrashDirOptions {
+    /// The trash directories that were picked.
+    pub fn trash_dirs(&self) -> Vec<TrashDir> {
+        TrashDir::all_from_opt(self.trash_dir.as_ref())
+    }
+}
+
+/// Option for how many threads directories are walked with
+#[derive(StructOpt)]
+pub struct ThreadOptions {
+    /// How many threads to copy, delete or measure directories with (0 picks a
+    /// number based on the number of CPUs)
+    #[structopt(long = "threads", short = "j", default_value = "0")]
+    threads: usize,
+}
+
+impl ThreadOptions {
+    /// Use this many threads from now on.
+    pub fn apply(&self) {
+        walk::set_threads(self.threads);
+    }
+}
+
+/// Parse a selection of indices like `1 3 5-7` or `all`, for a list of `len` items.
+///
+/// The indices are returned sorted and without duplicates.
+pub fn parse_selection(s: &str, len: usize) -> Result<Vec<usize>> {
+    let mut selected = BTreeSet::new();
+    for part in s.split(|c: char| c == ',' || c.is_whitespace()) {
+        let (start, end) = match part {
+            "" => continue,
+            "all" | "*" => (0, len.saturating_sub(1)),
+            _ => match part.find('-') {
+                Some(split) => (part[..split].parse()?, part[split + 1..].parse()?),
+                None => {
+                    let i = part.parse()?;
+                    (i, i)
+                }
+            },
+        };
+        ensure!(
+            start <= end && end < len,
+            "invalid selection '{}', expected numbers from 0 to {}",
+            part,
+            len.saturating_sub(1)
+        );
+        selected.extend(start..=end);
+    }
+    Ok(selected.into_iter().collect())
+}
+
+/// Turn a glob from the command line into a filter on original paths.
+///
+/// Patterns without a '/' are matched against the file name only, anything else is
+/// relative to the current directory.
+pub fn pattern_filter(pattern: &str) -> Result<Filter> {
+    Ok(Filter::Glob(if pattern.contains('/') {
+        absolute_pattern(pattern)?
+    } else {
+        Pattern::new(pattern)?
+    }))
+}
+
+/// Compile a glob pattern for matching original paths, relative to the current directory.
+fn absolute_pattern(pattern: &str) -> Result<Pattern> {
+    if pattern.starts_with('/') {
+        return Ok(Pattern::new(pattern)?);
+    }
+
+    let current_dir = env::current_dir()?.canonicalize()?;
+    let mut absolute = current_dir.to_str().map(Pattern::escape).ok_or_else(|| {
+        anyhow!(
+            "current directory {} isn't valid UTF-8",
+            current_dir.display()
+        )
+    })?;
+    if !absolute.ends_with('/') {
+        absolute.push('/');
+    }
+    absolute.push_str(pattern.trim_start_matches("./"));
+    Ok(Pattern::new(&absolute)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_selection;
+
+    #[test]
+    fn selections() {
+        assert_eq!(parse_selection("1 3 5-7", 10).unwrap(), vec![1, 3, 5, 6, 7]);
+        assert_eq!(parse_selection("3,1 1", 10).unwrap(), vec![1, 3]);
+        assert_eq!(parse_selection("2-4 3-5", 10).unwrap(), vec![2, 3, 4, 5]);
+        assert_eq!(parse_selection("all", 3).unwrap(), vec![0, 1, 2]);
+        assert_eq!(parse_selection("*", 2).unwrap(), vec![0, 1]);
+        assert_eq!(parse_selection("  ", 3).unwrap(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn invalid_selections() {
+        for s in &["10", "5-10", "7-5", "x", "1-", "-2", "1.5"] {
+            assert!(parse_selection(s, 10).is_err(), "{:?} should be invalid", s);
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::path::{Component, PathBuf};
+use std::slice;
+
+use anyhow::Result;
+
+use crate::ops::{read_entries, ThreadOptions, TrashDirOptions};
+use crate::utils;
+use crate::TrashInfo;
+
+/// Options to pass to stats
+#[derive(StructOpt)]
+pub struct StatsOptions {
+    /// Print sizes as a plain number of bytes
+    #[structopt(long = "bytes", short = "b")]
+    bytes: bool,
+
+    /// How many of the largest files to show for every trash directory
+    #[structopt(long = "largest", short = "n", default_value = "5")]
+    largest: usize,
+
+    /// How many levels of the original paths to group the files by
+    #[structopt(long = "depth", short = "d", default_value = "1")]
+    depth: usize,
+
+    #[structopt(flatten)]
+    trash_dir: TrashDirOptions,
+
+    #[structopt(flatten)]
+    threads: ThreadOptions,
+}
+
+/// Show how much space the trash takes up.
+pub fn stats(options: StatsOptions) -> Result<()> {
+    options.threads.apply();
+    let format_size = |size: u64| {
+        if options.bytes {
+            size.to_string()
+        } else {
+            utils::format_size(size)
+        }
+    };
+
+    let trash_dirs = options
+        .trash_dir
+        .trash_dirs()
+        .into_iter()
+        // don't create trash directories just to find out that they're empty
+        .filter(|trash_dir| matches!(trash_dir.check_info_dir(), Ok(Some(_))))
+        .collect::<Vec<_>>();
+
+    let mut total_count = 0;
+    let mut total_size = 0;
+    for trash_dir in trash_dirs.iter() {
+        // read_entries sorts them by deletion date, oldest first
+        let files = read_entries(slice::from_ref(trash_dir))
+            .into_iter()
+            .map(|info| {
+                // the size comes from directorysizes if it's there
+                let size = info.disk_usage().unwrap_or(0);
+                (info, size)
+            })
+            .collect::<Vec<_>>();
+        let size = files.iter().map(|(_, size)| size).sum::<u64>();
+        total_count += files.len();
+        total_size += size;
+
+        println!("{}", trash_dir.path().display());
+        println!("  files:\t{}", files.len());
+        println!("  size:\t{}", format_size(size));
+        if let (Some((oldest, _)), Some((newest, _))) = (files.first(), files.last()) {
+            println!(
+                "  oldest:\t{}\t{}",
+                oldest.deletion_date,
+                oldest.path.display()
+            );
+            println!(
+                "  newest:\t{}\t{}",
+                newest.deletion_date,
+                newest.path.display()
+            );
+        }
+
+        let mut largest = files.iter().collect::<Vec<_>>();
+        largest.sort_by(|(_, a), (_, b)| b.cmp(a));
+        largest.truncate(options.largest);
+        if !largest.is_empty() {
+            println!("  largest:");
+            for (info, size) in largest {
+                println!("    {}\t{}", format_size(*size), info.path.display());
+            }
+        }
+
+        let mut groups = BTreeMap::new();
+        for (info, size) in files.iter() {
+            let group = groups
+                .entry(group_path(info, options.depth))
+                .or_insert((0, 0));
+            group.0 += 1;
+            group.1 += size;
+        }
+        if !groups.is_empty() {
+            println!("  by directory:");
+            for (dir, (count, size)) in groups {
+                println!("    {}\t{}\t{}", format_size(size), count, dir.display());
+            }
+        }
+    }
+
+    if trash_dirs.len() > 1 {
+        println!(
+            "total: {} file(s), {}",
+            total_count,
+            format_size(total_size)
+        );
+    }
+
+    Ok(())
+}
+
+/// The directory that a file was originally in, cut off after `depth` levels.
+fn group_path(info: &TrashInfo, depth: usize) -> PathBuf {
+    let mut levels = 0;
+    info.path
+        .parent()
+        .unwrap_or(&info.path)
+        .components()
+        .take_while(|component| match component {
+            Component::Normal(_) => {
+                levels += 1;
+                levels <= depth
+            }
+            _ => true,
+        })
+        .collect()
+}
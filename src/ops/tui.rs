@@ -0,0 +1,625 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use unicode_width::UnicodeWidthChar;
+use walkdir::WalkDir;
+
+use crate::ops::empty::{delete_entry, prune_directory_sizes};
+use crate::ops::restore::{restore_entry, ConflictPolicy};
+use crate::ops::{read_entries_quietly, TrashDirOptions};
+use crate::term::{Key, Terminal};
+use crate::utils;
+use crate::{FileKind, SortKey, TrashDir, TrashInfo};
+
+/// How much of a file is read to preview it
+const PREVIEW_BYTES: u64 = 64 * 1024;
+
+/// How deep the preview of a directory goes
+const PREVIEW_DEPTH: usize = 3;
+
+/// How far page up and page down move
+const PAGE: isize = 10;
+
+const HELP: &str = "space select  a all  / search  s sort  r reverse  \
+                    enter restore  t restore to  D delete  q quit";
+
+/// Options to pass to tui
+#[derive(StructOpt)]
+pub struct TuiOptions {
+    #[structopt(flatten)]
+    trash_dir: TrashDirOptions,
+}
+
+/// Browse the trash in a full-screen interface.
+pub fn tui(options: TuiOptions) -> Result<()> {
+    let mut trash_dirs = options.trash_dir.trash_dirs();
+    trash_dirs.retain(|trash_dir| matches!(trash_dir.check_info_dir(), Ok(Some(_))));
+
+    let mut app = App::new(trash_dirs);
+    app.load();
+
+    let terminal = Terminal::open()?;
+    let mut size = (0, 0);
+    let mut dirty = true;
+    loop {
+        if dirty || terminal.size() != size {
+            size = terminal.size();
+            app.draw(&terminal)?;
+            dirty = false;
+        }
+
+        // time out every now and then to notice when the terminal is resized
+        if let Some(key) = terminal.read_key(Duration::from_millis(250))? {
+            if !app.handle_key(key) {
+                return Ok(());
+            }
+            dirty = true;
+        }
+    }
+}
+
+/// What the keyboard is being used for right now
+enum Mode {
+    /// Moving around the list
+    Normal,
+
+    /// Typing a search
+    Search,
+
+    /// Typing the directory to restore the entries with these .trashinfo files to
+    RestoreTo(String, Vec<PathBuf>),
+
+    /// Waiting for confirmation before deleting the entries with these .trashinfo
+    /// files for good
+    ConfirmDelete(Vec<PathBuf>),
+}
+
+struct App {
+    trash_dirs: Vec<TrashDir>,
+    files: Vec<TrashInfo>,
+
+    /// Indices into `files` of the entries that match the search, in display order
+    visible: Vec<usize>,
+
+    /// The .trashinfo files of the selected entries
+    selected: BTreeSet<PathBuf>,
+
+    /// Index into `visible` of the entry under the cursor
+    cursor: usize,
+
+    /// Index into `visible` of the first entry on screen
+    scroll: usize,
+
+    search: String,
+    sort: SortKey,
+    reverse: bool,
+    mode: Mode,
+    status: String,
+
+    /// The .trashinfo file of the entry that's being previewed, and its preview
+    preview: Option<(PathBuf, Vec<String>)>,
+}
+
+impl App {
+    fn new(trash_dirs: Vec<TrashDir>) -> Self {
+        App {
+            trash_dirs,
+            files: Vec::new(),
+            visible: Vec::new(),
+            selected: BTreeSet::new(),
+            cursor: 0,
+            scroll: 0,
+            search: String::new(),
+            sort: SortKey::Date,
+            reverse: false,
+            mode: Mode::Normal,
+            status: String::new(),
+            preview: None,
+        }
+    }
+
+    /// (Re)read every entry of the trash directories.
+    ///
+    /// Nothing can be printed while the interface is up, so entries that can't be read
+    /// are reported in the status line.
+    fn load(&mut self) {
+        let (files, errors) = read_entries_quietly(&self.trash_dirs);
+        self.files = files;
+        if let Some(first) = errors.first() {
+            if !self.status.is_empty() {
+                self.status.push_str("; ");
+            }
+            self.status += &format!("{} error(s) reading the trash: {}", errors.len(), first);
+        }
+        self.sort.sort(&mut self.files);
+
+        let files = &self.files;
+        self.selected
+            .retain(|path| files.iter().any(|info| info.info_path == *path));
+        self.preview = None;
+        self.refilter();
+    }
+
+    /// Work out which entries are shown after the search or the order changed,
+    /// keeping the cursor on the same entry if it's still there.
+    fn refilter(&mut self) {
+        let current = self.current().map(|info| info.info_path.clone());
+
+        let words = self
+            .search
+            .to_lowercase()
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        self.visible = self
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, info)| {
+                let path = info.path.to_string_lossy().to_lowercase();
+                words.iter().all(|word| path.contains(word.as_str()))
+            })
+            .map(|(i, _)| i)
+            .collect();
+        if self.reverse {
+            self.visible.reverse();
+        }
+
+        let files = &self.files;
+        self.cursor = current
+            .and_then(|current| {
+                self.visible
+                    .iter()
+                    .position(|&i| files[i].info_path == current)
+            })
+            .unwrap_or_else(|| self.cursor.min(self.visible.len().saturating_sub(1)));
+    }
+
+    fn current(&self) -> Option<&TrashInfo> {
+        self.visible.get(self.cursor).map(|&i| &self.files[i])
+    }
+
+    /// The entries that actions apply to: the selection, or else the one under the cursor.
+    fn targets(&self) -> Vec<&TrashInfo> {
+        if self.selected.is_empty() {
+            self.current().into_iter().collect()
+        } else {
+            self.files
+                .iter()
+                .filter(|info| self.selected.contains(&info.info_path))
+                .collect()
+        }
+    }
+
+    /// The .trashinfo files of the entries that actions apply to, so that they can be
+    /// held on to while a prompt is open.
+    fn target_paths(&self) -> Vec<PathBuf> {
+        self.targets()
+            .into_iter()
+            .map(|info| info.info_path.clone())
+            .collect()
+    }
+
+    fn move_cursor(&mut self, by: isize) {
+        let last = self.visible.len().saturating_sub(1) as isize;
+        self.cursor = (self.cursor as isize + by).max(0).min(last) as usize;
+    }
+
+    /// Handle a key press. Returns false when it's time to quit.
+    fn handle_key(&mut self, key: Key) -> bool {
+        // moving around works in every mode but the delete prompt, where any key
+        // answers it
+        let by = match key {
+            Key::Ctrl('c') => return false,
+            _ if matches!(self.mode, Mode::ConfirmDelete(_)) => None,
+            Key::Up => Some(-1),
+            Key::Down => Some(1),
+            Key::PageUp => Some(-PAGE),
+            Key::PageDown => Some(PAGE),
+            _ => None,
+        };
+        if let Some(by) = by {
+            self.move_cursor(by);
+            return true;
+        }
+
+        match self.mode {
+            Mode::Normal => return self.handle_normal_key(key),
+            Mode::Search => match key {
+                Key::Char(c) => {
+                    self.search.push(c);
+                    self.refilter();
+                }
+                Key::Backspace => {
+                    self.search.pop();
+                    self.refilter();
+                }
+                Key::Enter => self.mode = Mode::Normal,
+                Key::Esc => {
+                    self.search.clear();
+                    self.refilter();
+                    self.mode = Mode::Normal;
+                }
+                _ => (),
+            },
+            Mode::RestoreTo(ref mut dir, ref targets) => match key {
+                Key::Char(c) => dir.push(c),
+                Key::Backspace => {
+                    dir.pop();
+                }
+                Key::Enter => {
+                    let dir = expand_home(dir);
+                    let targets = targets.clone();
+                    self.mode = Mode::Normal;
+                    self.restore(&targets, Some(&dir));
+                }
+                Key::Esc => self.mode = Mode::Normal,
+                _ => (),
+            },
+            Mode::ConfirmDelete(ref targets) => {
+                let targets = targets.clone();
+                self.mode = Mode::Normal;
+                if key == Key::Char('y') || key == Key::Char('Y') {
+                    self.delete(&targets);
+                } else {
+                    self.status = "Not deleting anything.".to_owned();
+                }
+            }
+        }
+        true
+    }
+
+    fn handle_normal_key(&mut self, key: Key) -> bool {
+        self.status.clear();
+        match key {
+            Key::Char('q') => return false,
+            Key::Char('k') => self.move_cursor(-1),
+            Key::Char('j') => self.move_cursor(1),
+            Key::Ctrl('b') => self.move_cursor(-PAGE),
+            Key::Ctrl('f') => self.move_cursor(PAGE),
+            Key::Home | Key::Char('g') => self.cursor = 0,
+            Key::End | Key::Char('G') => self.cursor = self.visible.len().saturating_sub(1),
+            Key::Char(' ') => {
+                if let Some(path) = self.current().map(|info| info.info_path.clone()) {
+                    if !self.selected.remove(&path) {
+                        self.selected.insert(path);
+                    }
+                    self.move_cursor(1);
+                }
+            }
+            Key::Char('a') => {
+                let visible = self
+                    .visible
+                    .iter()
+                    .map(|&i| self.files[i].info_path.clone())
+                    .collect::<Vec<_>>();
+                if visible.iter().all(|path| self.selected.contains(path)) {
+                    for path in visible.iter() {
+                        self.selected.remove(path);
+                    }
+                } else {
+                    self.selected.extend(visible);
+                }
+            }
+            Key::Esc => {
+                if !self.search.is_empty() {
+                    self.search.clear();
+                    self.refilter();
+                } else {
+                    self.selected.clear();
+                }
+            }
+            Key::Char('/') => self.mode = Mode::Search,
+            Key::Char('s') => {
+                self.sort = match self.sort {
+                    SortKey::Date => SortKey::Path,
+                    SortKey::Path => SortKey::Size,
+                    SortKey::Size => SortKey::Name,
+                    SortKey::Name => SortKey::Date,
+                };
+                self.sort.sort(&mut self.files);
+                self.refilter();
+            }
+            Key::Char('r') => {
+                self.reverse = !self.reverse;
+                self.refilter();
+            }
+            Key::Ctrl('r') => self.load(),
+            Key::Enter | Key::Char('R') => {
+                let targets = self.target_paths();
+                self.restore(&targets, None);
+            }
+            Key::Char('t') => {
+                let mut dir = env::current_dir()
+                    .map(|dir| dir.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                if !dir.ends_with('/') {
+                    dir.push('/');
+                }
+                self.mode = Mode::RestoreTo(dir, self.target_paths());
+            }
+            Key::Char('D') | Key::Delete if !self.targets().is_empty() => {
+                self.mode = Mode::ConfirmDelete(self.target_paths());
+            }
+            _ => (),
+        }
+        true
+    }
+
+    /// Look up the entries with these .trashinfo files.
+    fn find(&self, info_paths: &[PathBuf]) -> Vec<&TrashInfo> {
+        self.files
+            .iter()
+            .filter(|info| info_paths.contains(&info.info_path))
+            .collect()
+    }
+
+    /// Restore the given entries, to where they came from or into `to`.
+    fn restore(&mut self, targets: &[PathBuf], to: Option<&Path>) {
+        let mut restored = 0;
+        let mut errors = Vec::new();
+        for info in self.find(targets) {
+            // a directory that was typed in is created, but the original
+            // locations of files are only restored to if they still exist
+            match restore_entry(info, to, ConflictPolicy::Abort, to.is_some()) {
+                Ok(_) => restored += 1,
+                Err(err) => errors.push(format!("{}: {}", info.path.display(), err)),
+            }
+        }
+
+        self.status = report("restored", restored, &errors);
+        self.load();
+    }
+
+    /// Delete the given entries for good.
+    fn delete(&mut self, targets: &[PathBuf]) {
+        let mut deleted = 0;
+        let mut errors = Vec::new();
+        for info in self.find(targets) {
+            match delete_entry(info) {
+                Ok(()) => deleted += 1,
+                Err(err) => errors.push(format!("{}: {}", info.path.display(), err)),
+            }
+        }
+
+        errors.extend(prune_directory_sizes(&self.trash_dirs));
+
+        self.status = report("deleted", deleted, &errors);
+        self.load();
+    }
+
+    fn draw(&mut self, terminal: &Terminal) -> Result<()> {
+        let (width, height) = terminal.size();
+        let list_height = height.saturating_sub(2);
+
+        // keep the cursor on screen
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        } else if self.cursor >= self.scroll + list_height {
+            self.scroll = self.cursor + 1 - list_height;
+        }
+
+        // the preview only fits next to the list on wide terminals
+        let (list_width, preview_width) = if width >= 80 {
+            let list_width = width * 3 / 5;
+            (list_width, width - list_width - 1)
+        } else {
+            (width, 0)
+        };
+        if preview_width > 0 {
+            self.update_preview(list_height);
+        }
+        let preview = match self.preview {
+            Some((_, ref lines)) if preview_width > 0 => lines.as_slice(),
+            _ => &[],
+        };
+
+        let sort = match self.sort {
+            SortKey::Date => "date",
+            SortKey::Path => "path",
+            SortKey::Size => "size",
+            SortKey::Name => "name",
+        };
+        let header = format!(
+            " garbage  {}/{} files  {} selected  sort: {}{}",
+            self.visible.len(),
+            self.files.len(),
+            self.selected.len(),
+            sort,
+            if self.reverse { " (reversed)" } else { "" },
+        );
+
+        let mut frame = String::from("\x1b[H");
+        frame.push_str("\x1b[7m");
+        frame.push_str(&fit(&header, width));
+        frame.push_str("\x1b[0m\r\n");
+
+        for row in 0..list_height {
+            let i = self.scroll + row;
+            match self.visible.get(i).map(|&index| &self.files[index]) {
+                Some(info) => {
+                    let selected = self.selected.contains(&info.info_path);
+                    let line = format!(
+                        "{} {}  {}",
+                        if selected { '*' } else { ' ' },
+                        info.deletion_date.format("%Y-%m-%d %H:%M"),
+                        info.path.display()
+                    );
+                    if i == self.cursor {
+                        frame.push_str("\x1b[7m");
+                    } else if selected {
+                        frame.push_str("\x1b[1m");
+                    }
+                    frame.push_str(&fit(&line, list_width));
+                    frame.push_str("\x1b[0m");
+                }
+                None => frame.push_str(&fit("", list_width)),
+            }
+            if preview_width > 0 {
+                frame.push('│');
+                let line = preview.get(row).map(String::as_str).unwrap_or("");
+                frame.push_str(&fit(line, preview_width));
+            }
+            frame.push_str("\r\n");
+        }
+
+        let footer = match self.mode {
+            Mode::Normal if !self.status.is_empty() => self.status.clone(),
+            Mode::Normal if self.files.is_empty() => "The trash is empty. q quit".to_owned(),
+            Mode::Normal => HELP.to_owned(),
+            Mode::Search => format!("/{}", self.search),
+            Mode::RestoreTo(ref dir, _) => format!("restore to: {}", dir),
+            Mode::ConfirmDelete(ref targets) => match self.find(targets).as_slice() {
+                [info] => format!("Permanently delete {}? [y/N]", info.path.display()),
+                infos => format!("Permanently delete {} files? [y/N]", infos.len()),
+            },
+        };
+        frame.push_str(&fit(&footer, width));
+
+        terminal.write(&frame)?;
+        Ok(())
+    }
+
+    /// Make sure the preview is of the entry under the cursor.
+    fn update_preview(&mut self, height: usize) {
+        let info = match self.current() {
+            Some(info) => info,
+            None => {
+                self.preview = None;
+                return;
+            }
+        };
+        if let Some((ref path, _)) = self.preview {
+            if *path == info.info_path {
+                return;
+            }
+        }
+        self.preview = Some((info.info_path.clone(), preview(info, height)));
+    }
+}
+
+/// Describe what happened to the entries an action was applied to.
+fn report(done: &str, count: usize, errors: &[String]) -> String {
+    match errors.first() {
+        None => format!("{} {} file(s)", done, count),
+        Some(first) => format!(
+            "{} {} file(s), {} failed: {}",
+            done,
+            count,
+            errors.len(),
+            first
+        ),
+    }
+}
+
+/// Describe an entry, and show what's in it.
+fn preview(info: &TrashInfo, height: usize) -> Vec<String> {
+    let mut lines = vec![
+        info.path.display().to_string(),
+        format!("deleted {}", info.deletion_date.format("%Y-%m-%d %H:%M:%S")),
+    ];
+
+    let kind = match info.file_kind() {
+        Ok(kind) => kind,
+        Err(err) => {
+            lines.push(format!("can't read file: {}", err));
+            return lines;
+        }
+    };
+    match info.size() {
+        Ok(size) => lines.push(format!("{}, {}", kind.as_str(), utils::format_size(size))),
+        Err(_) => lines.push(kind.as_str().to_owned()),
+    }
+    lines.push(String::new());
+
+    let room = height.saturating_sub(lines.len());
+    match kind {
+        FileKind::File => lines.extend(preview_file(&info.deleted_path, room)),
+        FileKind::Directory => {
+            let entries = WalkDir::new(&info.deleted_path)
+                .min_depth(1)
+                .max_depth(PREVIEW_DEPTH)
+                .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .take(room)
+                .map(|entry| {
+                    let mut line = "  ".repeat(entry.depth() - 1);
+                    line.push_str(&entry.file_name().to_string_lossy());
+                    if entry.file_type().is_dir() {
+                        line.push('/');
+                    }
+                    line
+                });
+            lines.extend(entries);
+        }
+        FileKind::Symlink => match fs::read_link(&info.deleted_path) {
+            Ok(target) => lines.push(format!("-> {}", target.display())),
+            Err(err) => lines.push(format!("can't read link: {}", err)),
+        },
+        FileKind::Other => (),
+    }
+    lines
+}
+
+/// The first lines of a file, if it looks like text.
+fn preview_file(path: &Path, count: usize) -> Vec<String> {
+    let mut contents = Vec::new();
+    if let Err(err) =
+        File::open(path).and_then(|file| file.take(PREVIEW_BYTES).read_to_end(&mut contents))
+    {
+        return vec![format!("can't read file: {}", err)];
+    }
+    if contents.contains(&0) {
+        return vec!["(binary file)".to_owned()];
+    }
+
+    String::from_utf8_lossy(&contents)
+        .lines()
+        .take(count)
+        .map(|line| line.replace('\t', "    "))
+        .collect()
+}
+
+/// Replace a leading `~/` with the home directory.
+fn expand_home(dir: &str) -> PathBuf {
+    match (dir.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(dir),
+    }
+}
+
+/// Cut or pad a string to exactly `width` columns, leaving out control characters.
+fn fit(s: &str, width: usize) -> String {
+    let chars = s
+        .chars()
+        .map(|c| if c.is_control() { '?' } else { c })
+        .map(|c| (c, c.width().unwrap_or(0)))
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+    let mut used = 0;
+    if chars.iter().map(|(_, w)| w).sum::<usize>() <= width {
+        out.extend(chars.iter().map(|(c, _)| c));
+        used = chars.iter().map(|(_, w)| w).sum();
+    } else if width > 0 {
+        // leave a column to show that something was cut off
+        for (c, w) in chars {
+            if used + w > width - 1 {
+                break;
+            }
+            out.push(c);
+            used += w;
+        }
+        out.push('…');
+        used += 1;
+    }
+    out.extend(iter::repeat_n(' ', width - used));
+    out
+}
@@ -0,0 +1,143 @@
+use anyhow::Result;
+
+use crate::journal::{self, Batch};
+use crate::ops::restore::{restore_entry, ConflictPolicy};
+use crate::ops::ThreadOptions;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Nothing to undo.")]
+    NothingToUndo,
+
+    #[error("No batch {0} in the journal, see --list")]
+    UnknownBatch(String),
+
+    #[error("Failed to restore {0} file(s), run undo again to retry them")]
+    Failed(usize),
+}
+
+/// Options to pass to undo
+#[derive(StructOpt)]
+pub struct UndoOptions {
+    /// The id of the batch to undo, as shown by --list.
+    /// By default, the most recent batch is undone
+    batch: Option<String>,
+
+    /// List the batches that can be undone, most recent first
+    #[structopt(long = "list", short = "l", conflicts_with = "batch")]
+    list: bool,
+
+    /// Don't actually restore anything, just print the files to be restored
+    #[structopt(long = "dry")]
+    dry: bool,
+
+    /// What to do if something already exists at the original path:
+    /// abort, overwrite (the existing file is trashed), rename (add a numeric suffix)
+    /// or prompt
+    #[structopt(long = "conflict", default_value = "abort")]
+    conflict: ConflictPolicy,
+
+    /// Create any missing parent directories of the path being restored to
+    #[structopt(long = "parents", short = "p")]
+    parents: bool,
+
+    #[structopt(flatten)]
+    threads: ThreadOptions,
+}
+
+/// Restore everything that a single put moved into the trash
+pub fn undo(options: UndoOptions) -> Result<()> {
+    options.threads.apply();
+    let batches = journal::read()?;
+
+    if options.list {
+        for batch in batches.iter().rev() {
+            print_batch(batch);
+        }
+        return Ok(());
+    }
+
+    let batch = match options.batch {
+        Some(ref id) => batches
+            .iter()
+            .rfind(|batch| batch.id == *id)
+            .ok_or_else(|| Error::UnknownBatch(id.clone()))?,
+        None => batches.last().ok_or(Error::NothingToUndo)?,
+    };
+
+    // in reverse, so that a directory comes back before anything that was inside it
+    let mut failed = 0;
+    let mut remaining = Vec::new();
+    for entry in batch.entries.iter().rev() {
+        let path = entry.path();
+        let info = match entry.find() {
+            Ok(Some(info)) => info,
+            Ok(None) => {
+                eprintln!("{} isn't in the trash anymore, skipping", path.display());
+                continue;
+            }
+            Err(err) => {
+                eprintln!(
+                    "failed to read the trash entry of {}: {}",
+                    path.display(),
+                    err
+                );
+                failed += 1;
+                remaining.push(entry.clone());
+                continue;
+            }
+        };
+
+        if options.dry {
+            eprintln!("Dry-restoring: {}", path.display());
+            continue;
+        }
+
+        match restore_entry(&info, None, options.conflict, options.parents) {
+            Ok(target) => eprintln!("restored {:?} to {:?}", info.path, target),
+            Err(err) => {
+                eprintln!("failed to restore {}: {}", path.display(), err);
+                failed += 1;
+                remaining.push(entry.clone());
+            }
+        }
+    }
+
+    if options.dry {
+        return Ok(());
+    }
+
+    // read the journal again, in case a put finished in the meantime, and only keep
+    // what's left to retry
+    let mut batches = journal::read()?;
+    if let Some(index) = batches.iter().rposition(|other| other.id == batch.id) {
+        if remaining.is_empty() {
+            batches.remove(index);
+        } else {
+            remaining.reverse();
+            batches[index].entries = remaining;
+        }
+        journal::write(&batches)?;
+    }
+
+    ensure!(failed == 0, Error::Failed(failed));
+    Ok(())
+}
+
+/// Print a line about a batch for --list.
+fn print_batch(batch: &Batch) {
+    let date = match batch.date() {
+        Some(date) => date.to_string(),
+        None => batch.date.clone(),
+    };
+    let first = batch
+        .entries
+        .first()
+        .map(|entry| entry.path().display().to_string())
+        .unwrap_or_default();
+    let more = match batch.entries.len() {
+        0 | 1 => String::new(),
+        n => format!(" and {} more", n - 1),
+    };
+    println!("{}\t{}\t{}{}", batch.id, date, first, more);
+}
@@ -0,0 +1,229 @@
+//! Paths that must never be trashed.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+
+use crate::utils;
+use crate::Error;
+use crate::{TrashDir, MOUNTS, XDG};
+
+/// Why a path is protected
+#[derive(Debug)]
+pub enum Reason {
+    Root,
+    Home,
+    ContainsHome,
+    MountPoint,
+    TrashDir(PathBuf),
+    ContainsTrashDir(PathBuf),
+    Pattern(String),
+    ContainsPattern(String),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Root => write!(f, "it's the root of the filesystem"),
+            Reason::Home => write!(f, "it's your home directory"),
+            Reason::ContainsHome => write!(f, "your home directory is inside of it"),
+            Reason::MountPoint => write!(f, "a filesystem is mounted on it"),
+            Reason::TrashDir(trash_dir) => {
+                write!(f, "it's the trash directory {}", trash_dir.display())
+            }
+            Reason::ContainsTrashDir(trash_dir) => write!(
+                f,
+                "the trash directory {} is inside of it",
+                trash_dir.display()
+            ),
+            Reason::Pattern(pattern) => {
+                write!(f, "it matches the protected pattern '{}'", pattern)
+            }
+            Reason::ContainsPattern(pattern) => write!(
+                f,
+                "what the protected pattern '{}' matches is inside of it",
+                pattern
+            ),
+        }
+    }
+}
+
+/// A glob from the user's list of protected paths
+#[derive(Debug)]
+struct Protected {
+    /// The line from the list, for error messages
+    source: String,
+
+    pattern: Pattern,
+
+    /// The part of the pattern up to the first wildcard, so that the directories
+    /// leading to whatever it matches are protected too
+    prefix: PathBuf,
+}
+
+/// Everything that's protected from being trashed.
+///
+/// That's the root of the filesystem, the home directory, mount points, trash
+/// directories (and every directory they're in), and whatever matches one of the
+/// globs in `$XDG_CONFIG_HOME/garbage/protected`.
+#[derive(Debug)]
+pub struct Protection {
+    homes: Vec<PathBuf>,
+    trash_dirs: Vec<PathBuf>,
+    patterns: Vec<Protected>,
+}
+
+impl Protection {
+    /// Find out what's protected, including the trash directory that files are
+    /// explicitly being put into, if there is one.
+    pub fn load(extra_trash_dir: Option<&TrashDir>) -> Result<Self, Error> {
+        // the home directory could be behind a symlink, which is protected as well
+        let mut homes = Vec::new();
+        if let Some(home) = env::var_os("HOME").map(PathBuf::from) {
+            homes.extend(resolve(&home).ok());
+            homes.extend(home.canonicalize().ok());
+        }
+
+        let mut trash_dirs = MOUNTS.trash_dirs();
+        trash_dirs.extend(extra_trash_dir.cloned());
+        let trash_dirs = trash_dirs
+            .iter()
+            .filter_map(|trash_dir| {
+                let path = trash_dir.path();
+                path.canonicalize()
+                    .ok()
+                    .or_else(|| utils::into_absolute(path).ok())
+            })
+            .collect();
+
+        Ok(Protection {
+            homes,
+            trash_dirs,
+            patterns: read_patterns()?,
+        })
+    }
+
+    /// Check whether path may be trashed, and if not, why.
+    ///
+    /// The last component of path isn't resolved, so a symlink to something
+    /// protected can still be trashed itself.
+    pub fn check(&self, path: &Path) -> Result<(), Reason> {
+        let path = match resolve(path) {
+            Ok(path) => path,
+            // nothing protected can be reached through a path that doesn't exist
+            Err(_) => return Ok(()),
+        };
+
+        if path == Path::new("/") {
+            return Err(Reason::Root);
+        }
+        for home in self.homes.iter() {
+            if *home == path {
+                return Err(Reason::Home);
+            } else if home.starts_with(&path) {
+                return Err(Reason::ContainsHome);
+            }
+        }
+        if MOUNTS.is_mount_point(&path) {
+            return Err(Reason::MountPoint);
+        }
+        for trash_dir in self.trash_dirs.iter() {
+            if *trash_dir == path {
+                return Err(Reason::TrashDir(trash_dir.clone()));
+            } else if trash_dir.starts_with(&path) {
+                return Err(Reason::ContainsTrashDir(trash_dir.clone()));
+            }
+        }
+
+        let match_options = MatchOptions {
+            require_literal_separator: true,
+            ..MatchOptions::new()
+        };
+        for protected in self.patterns.iter() {
+            if protected.pattern.matches_path_with(&path, match_options) {
+                return Err(Reason::Pattern(protected.source.clone()));
+            } else if protected.prefix.starts_with(&path) {
+                return Err(Reason::ContainsPattern(protected.source.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the user's protected globs, one per line.
+///
+/// Blank lines and lines starting with `#` are skipped. Patterns have to be
+/// absolute or start with `~/`, and ones that aren't valid are reported and skipped.
+fn read_patterns() -> Result<Vec<Protected>, Error> {
+    let path = XDG.get_config_home().join("garbage").join("protected");
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::from(err)),
+    };
+
+    let mut patterns = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let expanded = match (line.strip_prefix("~/"), env::var("HOME")) {
+            (Some(rest), Ok(home)) => {
+                format!("{}/{}", Pattern::escape(home.trim_end_matches('/')), rest)
+            }
+            _ => line.to_owned(),
+        };
+        if !expanded.starts_with('/') {
+            eprintln!(
+                "{}:{}: ignoring '{}', protected patterns have to be absolute or start with ~/",
+                path.display(),
+                i + 1,
+                line
+            );
+            continue;
+        }
+        let pattern = match Pattern::new(expanded.trim_end_matches('/')) {
+            Ok(pattern) => pattern,
+            Err(err) => {
+                eprintln!("{}:{}: ignoring '{}': {}", path.display(), i + 1, line, err);
+                continue;
+            }
+        };
+
+        let prefix = Path::new(&expanded)
+            .components()
+            .take_while(|component| match component {
+                Component::Normal(name) => !name.to_string_lossy().contains(['*', '?', '[']),
+                _ => true,
+            })
+            .collect();
+        patterns.push(Protected {
+            source: line.to_owned(),
+            pattern,
+            prefix,
+        });
+    }
+    Ok(patterns)
+}
+
+/// Make path absolute and resolve symlinks in it, except in its last component.
+fn resolve(path: &Path) -> io::Result<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => {
+            let parent = if parent.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                parent
+            };
+            Ok(parent.canonicalize()?.join(name))
+        }
+        _ => path.canonicalize(),
+    }
+}
@@ -0,0 +1,188 @@
+//! Just enough terminal handling for a full-screen interface.
+
+use std::io::{self, Write};
+use std::mem;
+use std::time::Duration;
+
+/// A key that was pressed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Delete,
+    Backspace,
+    Enter,
+    Tab,
+    Esc,
+}
+
+/// The terminal in raw mode on the alternate screen.
+///
+/// The terminal is put back the way it was when this is dropped.
+pub struct Terminal {
+    original: libc::termios,
+}
+
+impl Terminal {
+    /// Switch the terminal to raw mode and to the alternate screen.
+    pub fn open() -> Result<Self, io::Error> {
+        if unsafe {
+            libc::isatty(libc::STDIN_FILENO) == 0 || libc::isatty(libc::STDOUT_FILENO) == 0
+        } {
+            return Err(io::Error::other("stdin and stdout have to be a terminal"));
+        }
+
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = original;
+        raw.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+        raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let terminal = Terminal { original };
+        // alternate screen, hidden cursor
+        terminal.write("\x1b[?1049h\x1b[?25l")?;
+        Ok(terminal)
+    }
+
+    /// Get the number of columns and rows of the terminal.
+    pub fn size(&self) -> (usize, usize) {
+        let mut size: libc::winsize = unsafe { mem::zeroed() };
+        let ret = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+        if ret != 0 || size.ws_col == 0 || size.ws_row == 0 {
+            return (80, 24);
+        }
+        (size.ws_col.into(), size.ws_row.into())
+    }
+
+    /// Write a whole frame to the terminal at once.
+    pub fn write(&self, s: &str) -> Result<(), io::Error> {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        stdout.write_all(s.as_bytes())?;
+        stdout.flush()
+    }
+
+    /// Wait for a key press, for at most `timeout`.
+    pub fn read_key(&self, timeout: Duration) -> Result<Option<Key>, io::Error> {
+        if !wait_for_input(timeout)? {
+            return Ok(None);
+        }
+
+        let key = match read_byte()? {
+            b'\x1b' => {
+                // a lone escape isn't followed by anything right away
+                if !wait_for_input(Duration::from_millis(25))? {
+                    return Ok(Some(Key::Esc));
+                }
+                match read_byte()? {
+                    b'[' | b'O' => read_escape_sequence()?,
+                    _ => None,
+                }
+            }
+            b'\r' | b'\n' => Some(Key::Enter),
+            b'\t' => Some(Key::Tab),
+            b'\x7f' | b'\x08' => Some(Key::Backspace),
+            b @ 1..=26 => Some(Key::Ctrl((b'a' + b - 1) as char)),
+            b if b < 0x80 => Some(Key::Char(b as char)),
+            b => {
+                // the rest of a UTF-8 encoded character
+                let len = match b {
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    _ => 4,
+                };
+                let mut buf = vec![b];
+                for _ in 1..len {
+                    buf.push(read_byte()?);
+                }
+                String::from_utf8(buf)
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .map(Key::Char)
+            }
+        };
+        Ok(key)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.write("\x1b[?25h\x1b[?1049l");
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original) };
+    }
+}
+
+/// Read what comes after `ESC [`, like `A` for the up arrow or `5~` for page up.
+fn read_escape_sequence() -> Result<Option<Key>, io::Error> {
+    let mut params = String::new();
+    loop {
+        let b = read_byte()?;
+        if (0x40..=0x7e).contains(&b) {
+            return Ok(match (params.as_str(), b) {
+                (_, b'A') => Some(Key::Up),
+                (_, b'B') => Some(Key::Down),
+                (_, b'C') => Some(Key::Right),
+                (_, b'D') => Some(Key::Left),
+                (_, b'H') | ("1", b'~') | ("7", b'~') => Some(Key::Home),
+                (_, b'F') | ("4", b'~') | ("8", b'~') => Some(Key::End),
+                ("3", b'~') => Some(Key::Delete),
+                ("5", b'~') => Some(Key::PageUp),
+                ("6", b'~') => Some(Key::PageDown),
+                _ => None,
+            });
+        }
+        params.push(b as char);
+    }
+}
+
+fn wait_for_input(timeout: Duration) -> Result<bool, io::Error> {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    match unsafe { libc::poll(&mut fd, 1, timeout.as_millis() as libc::c_int) } {
+        -1 => {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                Ok(false)
+            } else {
+                Err(err)
+            }
+        }
+        n => Ok(n > 0),
+    }
+}
+
+fn read_byte() -> Result<u8, io::Error> {
+    // not through io::stdin(), since its buffer would hide input from poll
+    let mut buf = [0u8];
+    loop {
+        match unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr() as *mut libc::c_void, 1) } {
+            1 => return Ok(buf[0]),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            _ => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Parsing of human-friendly durations and dates.
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+use crate::Error;
+
+/// The longest duration that's accepted, so that it can always be subtracted from now.
+const MAX_DURATION_SECS: i64 = 100_000 * 365 * 24 * 60 * 60;
+
+/// Parse a duration like `90s`, `36h`, `2w` or `1d12h`.
+///
+/// The units are `s`, `m` (minutes), `h`, `d`, `w` and `y` (365 days). A plain number
+/// without any unit is a number of days.
+pub fn parse_duration(s: &str) -> Result<Duration, Error> {
+    let invalid = || Error::InvalidDuration(s.to_owned());
+
+    let trimmed = s.trim();
+    if trimmed.is_empty() {
+        return Err(invalid());
+    }
+
+    // a plain number is a number of days
+    let with_unit;
+    let trimmed = if trimmed.bytes().all(|b| b.is_ascii_digit()) {
+        with_unit = format!("{}d", trimmed);
+        with_unit.as_str()
+    } else {
+        trimmed
+    };
+
+    let mut total = Duration::zero();
+    let mut rest = trimmed;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(invalid)?;
+        let (number, tail) = rest.split_at(split);
+        let number = number.parse::<i64>().map_err(|_| invalid())?;
+
+        let split = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(split);
+        let seconds = match unit.trim() {
+            "s" | "sec" | "secs" | "second" | "seconds" => 1,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60,
+            "h" | "hour" | "hours" => 60 * 60,
+            "d" | "day" | "days" => 24 * 60 * 60,
+            "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+            "y" | "year" | "years" => 365 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+
+        let seconds = number
+            .checked_mul(seconds)
+            .filter(|seconds| *seconds <= MAX_DURATION_SECS)
+            .ok_or_else(invalid)?;
+        total = total
+            .checked_add(&Duration::seconds(seconds))
+            .ok_or_else(invalid)?;
+        if total.num_seconds() > MAX_DURATION_SECS {
+            return Err(invalid());
+        }
+        rest = tail.trim_start();
+    }
+
+    Ok(total)
+}
+
+/// Parse a date.
+///
+/// This is either an absolute date (`2026-01-01`, `2026-01-01T12:00:00`,
+/// `2026-01-01 12:00:00` or RFC 3339) in local time unless an offset is given, or
+/// a duration (see [`parse_duration`]) meaning that long before now.
+pub fn parse_date(s: &str) -> Result<DateTime<Local>, Error> {
+    let s = s.trim();
+
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Ok(date.with_timezone(&Local));
+    }
+
+    let naive = [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+    });
+    if let Some(naive) = naive {
+        return Local
+            .from_local_datetime(&naive)
+            .earliest()
+            .ok_or_else(|| Error::InvalidDate(s.to_owned()));
+    }
+
+    parse_duration(s)
+        .ok()
+        .and_then(|duration| Local::now().checked_sub_signed(duration))
+        .ok_or_else(|| Error::InvalidDate(s.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+    use super::{parse_date, parse_duration};
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90s").unwrap(), Duration::seconds(90));
+        assert_eq!(parse_duration("36h").unwrap(), Duration::hours(36));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::weeks(2));
+        assert_eq!(parse_duration("1d12h").unwrap(), Duration::hours(36));
+        assert_eq!(parse_duration("1d 12h").unwrap(), Duration::hours(36));
+        assert_eq!(parse_duration("3").unwrap(), Duration::days(3));
+        assert_eq!(parse_duration("2 days").unwrap(), Duration::days(2));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        for s in &["", "h", "1x", "1.5d", "-1d"] {
+            assert!(parse_duration(s).is_err(), "{:?} should be invalid", s);
+        }
+    }
+
+    #[test]
+    fn overflowing_durations() {
+        assert!(parse_duration("99999999999999999999s").is_err());
+        assert!(parse_duration("9223372036854775807y").is_err());
+        assert!(parse_duration("100000y 100000y").is_err());
+    }
+
+    /// 2026-01-02 at this time of day.
+    fn jan_2(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 1, 2)
+            .and_then(|date| date.and_hms_opt(hour, minute, 0))
+            .unwrap()
+    }
+
+    #[test]
+    fn dates() {
+        let midnight = Local.from_local_datetime(&jan_2(0, 0)).unwrap();
+        assert_eq!(parse_date("2026-01-02").unwrap(), midnight);
+        let noon = Local.from_local_datetime(&jan_2(12, 30)).unwrap();
+        assert_eq!(parse_date("2026-01-02 12:30").unwrap(), noon);
+        assert_eq!(parse_date("2026-01-02T12:30:00").unwrap(), noon);
+        assert_eq!(
+            parse_date("2026-01-02T12:30:00Z").unwrap(),
+            Utc.from_utc_datetime(&jan_2(12, 30))
+        );
+    }
+
+    #[test]
+    fn relative_dates() {
+        let date = parse_date("1d").unwrap();
+        let expected = Local::now() - Duration::days(1);
+        assert!((expected - date).num_seconds().abs() < 5);
+        assert!(parse_date("yesterday").is_err());
+    }
+}
@@ -1,9 +1,19 @@
 use std::env;
+use std::ffi::{CString, OsString};
 use std::fs;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Mutex;
 
+use crate::copy;
+use crate::errors::WalkErrors;
+use crate::walk::Walker;
 use anyhow::Result;
-use walkdir::WalkDir;
 
 pub fn into_absolute(path: impl AsRef<Path>) -> Result<PathBuf> {
     let path = path.as_ref();
@@ -15,37 +25,250 @@ pub fn into_absolute(path: impl AsRef<Path>) -> Result<PathBuf> {
     })
 }
 
+/// Parses a size like `512`, `20K`, `1.5M` or `20GiB` into a number of bytes.
+///
+/// Suffixes are powers of 1024, and an optional trailing `B` or `iB` is ignored.
+pub fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| anyhow!("invalid size '{}'", s))?;
+
+    let suffix = suffix.trim();
+    let suffix = suffix
+        .strip_suffix("iB")
+        .or_else(|| suffix.strip_suffix('B'))
+        .unwrap_or(suffix);
+    let multiplier: u64 = match suffix.to_uppercase().as_str() {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        "P" => 1 << 50,
+        _ => bail!("invalid size suffix in '{}'", s),
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Formats a number of bytes like `512B`, `20.0K` or `1.5G`, using powers of 1024.
+pub fn format_size(size: u64) -> String {
+    const SUFFIXES: [&str; 5] = ["K", "M", "G", "T", "P"];
+
+    if size < 1024 {
+        return format!("{}B", size);
+    }
+    let mut size = size as f64 / 1024.0;
+    let mut suffix = SUFFIXES[0];
+    for next in SUFFIXES[1..].iter() {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        suffix = next;
+    }
+    format!("{:.1}{}", size, suffix)
+}
+
+/// Gets the number of bytes available to unprivileged users on the filesystem holding path.
+pub fn free_space(path: impl AsRef<Path>) -> Result<u64> {
+    let path = CString::new(path.as_ref().as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+extern "C" {
+    // not in the libc crate yet
+    fn syncfs(fd: libc::c_int) -> libc::c_int;
+}
+
+/// Flushes everything on the filesystem holding path to disk.
+pub fn sync_filesystem(path: impl AsRef<Path>) -> Result<()> {
+    let file = fs::File::open(path)?;
+    if unsafe { syncfs(file.as_raw_fd()) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Checks whether a process with this pid is running.
+pub fn process_exists(pid: libc::pid_t) -> bool {
+    // a process of another user still exists, we just can't signal it
+    let ret = unsafe { libc::kill(pid, 0) };
+    ret == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Checks whether stdin is a terminal that someone could answer prompts on.
+pub fn stdin_is_terminal() -> bool {
+    unsafe { libc::isatty(libc::STDIN_FILENO) == 1 }
+}
+
 pub fn get_uid() -> u64 {
     unsafe { libc::getuid().into() }
 }
 
-/// This function recursively copies all the contents of src into dst.
-pub fn recursive_copy(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<()> {
+/// Removes a file or a whole directory tree, without following symlinks.
+pub fn remove_all(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    if !path.symlink_metadata()?.is_dir() {
+        fs::remove_file(path)?;
+        return Ok(());
+    }
+
+    // everything but the directories is removed in parallel, and then the
+    // directories are removed deepest first, once they're empty
+    let dirs = Mutex::new(Vec::new());
+    Walker::new().run(path, |entry| {
+        if entry.metadata.is_dir() {
+            dirs.lock().unwrap().push((entry.depth, entry.path.clone()));
+            Ok(())
+        } else {
+            fs::remove_file(&entry.path)
+        }
+    })?;
+
+    let mut dirs = dirs.into_inner().unwrap();
+    dirs.sort_by(|a, b| b.cmp(a));
+    for (_, dir) in dirs {
+        fs::remove_dir(dir)?;
+    }
+    fs::remove_dir(path)?;
+    Ok(())
+}
+
+/// Like remove_all, but if that fails because of a read-only directory inside of
+/// path, every directory in there is made writable and it's tried again.
+pub fn force_remove_all(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    match remove_all(path) {
+        Err(err) if is_permission_denied(&err) => {
+            let make_writable = |path: &Path, meta: &fs::Metadata| {
+                let mut perms = meta.permissions();
+                perms.set_mode(perms.mode() | 0o700);
+                fs::set_permissions(path, perms)
+            };
+
+            make_writable(path, &path.symlink_metadata()?)?;
+            Walker::new().run(path, |entry| {
+                if entry.metadata.is_dir() {
+                    make_writable(&entry.path, &entry.metadata)?;
+                }
+                Ok(())
+            })?;
+            remove_all(path)
+        }
+        result => result,
+    }
+}
+
+fn is_permission_denied(err: &anyhow::Error) -> bool {
+    if let Some(err) = err.downcast_ref::<io::Error>() {
+        err.kind() == io::ErrorKind::PermissionDenied
+    } else if let Some(errors) = err.downcast_ref::<WalkErrors>() {
+        errors.any_of_kind(io::ErrorKind::PermissionDenied)
+    } else {
+        false
+    }
+}
+
+/// The flag for renameat2 that makes it fail instead of replacing the target
+const RENAME_NOREPLACE: libc::c_uint = 1;
+
+/// Renames src to dst, failing with `AlreadyExists` instead of replacing anything
+/// that's at dst.
+///
+/// On filesystems without `RENAME_NOREPLACE`, files are linked to dst and then
+/// unlinked, and directories (which can't be linked) are only renamed if nothing
+/// is at dst right before.
+pub fn rename_noreplace(src: &Path, dst: &Path) -> io::Result<()> {
+    let to_cstring = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    };
+    let (src_c, dst_c) = (to_cstring(src)?, to_cstring(dst)?);
+    let ret = unsafe {
+        libc::syscall(
+            libc::SYS_renameat2,
+            libc::AT_FDCWD,
+            src_c.as_ptr(),
+            libc::AT_FDCWD,
+            dst_c.as_ptr(),
+            RENAME_NOREPLACE,
+        )
+    };
+    if ret == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EINVAL) | Some(libc::ENOSYS) => (),
+        _ => return Err(err),
+    }
+
+    if src.symlink_metadata()?.is_dir() {
+        if dst.symlink_metadata().is_ok() {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        return fs::rename(src, dst);
+    }
+    fs::hard_link(src, dst)?;
+    fs::remove_file(src)
+}
+
+/// Moves src to dst, without ever replacing anything that's at dst.
+///
+/// If they're on different filesystems (rename fails with EXDEV), src is copied
+/// over and then removed instead. The copy is made under a temporary name next to
+/// dst and only renamed into place once it's complete, so a failed copy never
+/// leaves anything at dst.
+pub fn move_path(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> Result<()> {
     let src = src.as_ref();
     let dst = dst.as_ref();
 
-    for entry in WalkDir::new(src)
-        .contents_first(false)
-        .follow_links(false)
-        .same_file_system(true)
-    {
-        let entry = entry?;
-        let path = entry.path();
-        let relative_path = path.strip_prefix(src)?;
-
-        // this must be the root
-        if let None = relative_path.file_name() {
-            fs::create_dir(dst)?;
-            continue;
-        }
+    match rename_noreplace(src, dst) {
+        Ok(()) => Ok(()),
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            let name = dst
+                .file_name()
+                .ok_or_else(|| anyhow!("couldn't get file name of {}", dst.display()))?;
+            let mut temp_name = OsString::from(".");
+            temp_name.push(name);
+            temp_name.push(format!(".garbage-{}", process::id()));
+            let temp = dst.with_file_name(temp_name);
 
-        let target_name = dst.join(relative_path);
-        if path.is_dir() {
-            fs::create_dir(&target_name)?;
-        } else {
-            fs::copy(path, &target_name)?;
+            copy::copy_into_place(src, &temp, || Ok(rename_noreplace(&temp, dst)?))?;
+            remove_all(src)
         }
+        Err(err) => Err(err.into()),
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::parse_size;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("20K").unwrap(), 20 * 1024);
+        assert_eq!(parse_size("1.5M").unwrap(), 3 * 512 * 1024);
+        assert_eq!(parse_size("2GiB").unwrap(), 2 << 30);
+        assert_eq!(parse_size("3kB").unwrap(), 3 * 1024);
+        assert_eq!(parse_size("7B").unwrap(), 7);
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        for s in &["", "M", "12X", "1.2.3K", "-5"] {
+            assert!(parse_size(s).is_err(), "{:?} should be invalid", s);
+        }
+    }
 }
@@ -0,0 +1,176 @@
+//! Walking directory trees with several threads at once.
+
+use std::fs::{self, Metadata};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use crate::errors::WalkErrors;
+
+/// The number of threads to walk with, or 0 to pick one based on the number of CPUs
+static THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// More threads than this rarely help, since the disk becomes the bottleneck
+const MAX_DEFAULT_THREADS: usize = 8;
+
+/// Set the number of threads that directory trees are walked with.
+///
+/// 0 picks a number based on the number of CPUs.
+pub fn set_threads(threads: usize) {
+    THREADS.store(threads, Ordering::Relaxed);
+}
+
+fn threads() -> usize {
+    match THREADS.load(Ordering::Relaxed) {
+        0 => thread::available_parallelism()
+            .map(|n| n.get().min(MAX_DEFAULT_THREADS))
+            .unwrap_or(1),
+        n => n,
+    }
+}
+
+/// Something that was found while walking a directory tree
+pub struct Entry {
+    /// The path of the entry, starting with the root of the walk
+    pub path: PathBuf,
+
+    /// How deep the entry is, where the direct children of the root are 1
+    pub depth: usize,
+
+    /// The metadata of the entry itself, without following symlinks
+    pub metadata: Metadata,
+}
+
+/// A directory tree walker that visits entries from several threads
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Walker {
+    same_file_system: bool,
+}
+
+/// What's shared between the threads of a walk
+struct State {
+    /// Directories that still have to be read
+    queue: Vec<(PathBuf, usize)>,
+
+    /// How many directories are being read right now
+    busy: usize,
+
+    errors: Vec<(PathBuf, io::Error)>,
+}
+
+impl Walker {
+    /// Create a walker with the default settings.
+    pub fn new() -> Self {
+        Walker::default()
+    }
+
+    /// Don't descend into directories on other filesystems than the root.
+    pub fn same_file_system(mut self, yes: bool) -> Self {
+        self.same_file_system = yes;
+        self
+    }
+
+    /// Call `f` on every entry below `root` (but not on `root` itself).
+    ///
+    /// Entries are visited from several threads in no particular order, except that a
+    /// directory is always visited before anything inside of it. If `f` fails for a
+    /// directory, its contents are skipped. Symlinks are never followed.
+    ///
+    /// All errors are collected, both from `f` and from reading the tree, and they're
+    /// returned together sorted by path, so the same tree always fails the same way.
+    pub fn run<F>(&self, root: &Path, f: F) -> Result<(), WalkErrors>
+    where
+        F: Fn(&Entry) -> io::Result<()> + Sync,
+    {
+        let root_dev = match root.symlink_metadata() {
+            Ok(meta) => meta.dev(),
+            Err(err) => return Err(WalkErrors(vec![(root.to_path_buf(), err)])),
+        };
+
+        let state = Mutex::new(State {
+            queue: vec![(root.to_path_buf(), 0)],
+            busy: 0,
+            errors: Vec::new(),
+        });
+        let changed = Condvar::new();
+
+        let work = || loop {
+            let (dir, depth) = {
+                let mut state = state.lock().unwrap();
+                loop {
+                    if let Some(job) = state.queue.pop() {
+                        state.busy += 1;
+                        break job;
+                    }
+                    if state.busy == 0 {
+                        return;
+                    }
+                    state = changed.wait(state).unwrap();
+                }
+            };
+
+            let mut subdirs = Vec::new();
+            let mut errors = Vec::new();
+            match fs::read_dir(&dir) {
+                Ok(entries) => {
+                    for entry in entries {
+                        let entry = match entry.and_then(|entry| {
+                            let metadata = entry.metadata()?;
+                            Ok(Entry {
+                                path: entry.path(),
+                                depth: depth + 1,
+                                metadata,
+                            })
+                        }) {
+                            Ok(entry) => entry,
+                            Err(err) => {
+                                errors.push((dir.clone(), err));
+                                continue;
+                            }
+                        };
+
+                        match f(&entry) {
+                            Ok(()) => {
+                                let meta = &entry.metadata;
+                                if meta.is_dir()
+                                    && !(self.same_file_system && meta.dev() != root_dev)
+                                {
+                                    subdirs.push((entry.path, entry.depth));
+                                }
+                            }
+                            Err(err) => errors.push((entry.path, err)),
+                        }
+                    }
+                }
+                Err(err) => errors.push((dir, err)),
+            }
+
+            let mut state = state.lock().unwrap();
+            state.busy -= 1;
+            state.queue.extend(subdirs);
+            state.errors.extend(errors);
+            changed.notify_all();
+        };
+
+        let threads = threads();
+        if threads <= 1 {
+            work();
+        } else {
+            thread::scope(|scope| {
+                for _ in 0..threads {
+                    scope.spawn(work);
+                }
+            });
+        }
+
+        let mut errors = state.into_inner().unwrap().errors;
+        if errors.is_empty() {
+            return Ok(());
+        }
+        errors.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Err(WalkErrors(errors))
+    }
+}